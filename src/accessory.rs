@@ -0,0 +1,65 @@
+use crate::keylight::Status;
+use serde::Deserialize;
+
+/// What a light reports about itself at `/elgato/accessory-info`. Older firmware leaves some
+/// of these out, so missing fields read as empty.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AccessoryInfo {
+    pub product_name: String,
+    pub hardware_board_type: u32,
+    pub firmware_version: String,
+    pub firmware_build_number: u32,
+    pub serial_number: String,
+    pub display_name: String,
+    pub features: Vec<String>,
+}
+
+impl AccessoryInfo {
+    /// Whether the device can show colors rather than only shades of white. Lights showing a
+    /// color report their hue; a Light Strip showing white may not, so its name counts too.
+    pub fn supports_color(&self, status: &Status) -> bool {
+        status.lights.iter().any(|light| light.hue.is_some())
+            || self.product_name.contains("Light Strip")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn info(product_name: &str) -> AccessoryInfo {
+        AccessoryInfo {
+            product_name: product_name.to_string(),
+            features: vec!["lights".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn status(light: serde_json::Value) -> Status {
+        serde_json::from_value(json!({ "numberOfLights": 1, "lights": [light] })).unwrap()
+    }
+
+    #[test]
+    fn color_support_follows_what_the_device_reports() {
+        let white = status(json!({ "on": 1, "brightness": 35, "temperature": 222 }));
+        let color = status(json!({ "on": 1, "brightness": 80, "hue": 30.0, "saturation": 100.0 }));
+
+        for product_name in [
+            "Elgato Key Light",
+            "Elgato Key Light Air",
+            "Elgato Key Light Mini",
+            "Elgato Ring Light",
+        ] {
+            assert!(
+                !info(product_name).supports_color(&white),
+                "{}",
+                product_name
+            );
+        }
+        assert!(info("Elgato Light Strip").supports_color(&white));
+        assert!(info("Elgato Light Strip").supports_color(&color));
+        assert!(info("").supports_color(&color));
+    }
+}
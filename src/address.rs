@@ -0,0 +1,259 @@
+use crate::discovery;
+use crate::error::Error;
+use crate::keylight::PORT;
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Where to reach a light: an IPv4 or IPv6 address or a hostname, and the port its API listens
+/// on. Written as `192.168.1.40`, `keylight.local:9124`, `fe80::1%en0`, or `[fe80::1%en0]:9124`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    host: Host,
+    port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Host {
+    /// An IP address, with the interface index an IPv6 link-local address is scoped to.
+    Ip(IpAddr, u32),
+    Name(String),
+}
+
+impl Address {
+    /// The host to put in a request URL. Hostnames are resolved ahead of time and handed to
+    /// the client, and so are scoped IPv6 addresses, since URLs cannot carry a zone.
+    pub fn url_host(&self) -> String {
+        match &self.host {
+            Host::Ip(IpAddr::V4(ip), _) => ip.to_string(),
+            Host::Ip(IpAddr::V6(ip), 0) => format!("[{}]", ip),
+            Host::Ip(IpAddr::V6(_), _) => "link-local.invalid".to_string(),
+            Host::Name(name) => name.clone(),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Finds the socket addresses to try connecting to, using the system resolver for
+    /// hostnames and falling back to asking over mDNS for `.local` names it does not know.
+    pub async fn resolve(&self, timeout: Duration) -> Result<Vec<SocketAddr>, Error> {
+        let name = match &self.host {
+            Host::Ip(IpAddr::V6(ip), scope) => {
+                return Ok(vec![SocketAddrV6::new(*ip, self.port, 0, *scope).into()]);
+            }
+            Host::Ip(ip, _) => return Ok(vec![SocketAddr::new(*ip, self.port)]),
+            Host::Name(name) => name,
+        };
+
+        if let Ok(addresses) = tokio::net::lookup_host((name.as_str(), self.port)).await {
+            let addresses: Vec<SocketAddr> = addresses.collect();
+            if !addresses.is_empty() {
+                return Ok(addresses);
+            }
+        }
+
+        let hostname = name.trim_end_matches('.');
+        if hostname.ends_with(".local") {
+            if let Some(address) = discovery::resolve_hostname(hostname, self.port, timeout).await?
+            {
+                return Ok(vec![address]);
+            }
+        }
+
+        Err(Error::Unreachable(format!(
+            "{} (the name could not be resolved)",
+            self
+        )))
+    }
+}
+
+impl From<SocketAddr> for Address {
+    fn from(address: SocketAddr) -> Self {
+        let scope = match address {
+            SocketAddr::V6(address) => address.scope_id(),
+            SocketAddr::V4(_) => 0,
+        };
+        Address {
+            host: Host::Ip(address.ip(), scope),
+            port: address.port(),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let host = match &self.host {
+            Host::Ip(IpAddr::V6(ip), 0) if self.port != PORT => format!("[{}]", ip),
+            Host::Ip(IpAddr::V6(ip), scope) if self.port != PORT => format!("[{}%{}]", ip, scope),
+            Host::Ip(IpAddr::V6(ip), 0) => ip.to_string(),
+            Host::Ip(IpAddr::V6(ip), scope) => format!("{}%{}", ip, scope),
+            Host::Ip(ip, _) => ip.to_string(),
+            Host::Name(name) => name.clone(),
+        };
+
+        if self.port == PORT {
+            write!(f, "{}", host)
+        } else {
+            write!(f, "{}:{}", host, self.port)
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not an IP address, hostname, or host:port", s);
+
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']').ok_or_else(invalid)?;
+            let port = match rest {
+                "" => None,
+                _ => Some(rest.strip_prefix(':').ok_or_else(invalid)?),
+            };
+            (host, port)
+        } else if s.matches(':').count() > 1 {
+            // More than one colon without brackets can only be a bare IPv6 address.
+            (s, None)
+        } else {
+            match s.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (s, None),
+            }
+        };
+
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None => PORT,
+        };
+
+        let host = if let Ok(ip) = IpAddr::from_str(host) {
+            Host::Ip(ip, 0)
+        } else if let Some((ip, zone)) = host.split_once('%') {
+            let ip = Ipv6Addr::from_str(ip).map_err(|_| invalid())?;
+            Host::Ip(IpAddr::V6(ip), interface_index(zone).ok_or_else(invalid)?)
+        } else if is_hostname(host) {
+            Host::Name(host.to_ascii_lowercase())
+        } else {
+            return Err(invalid());
+        };
+
+        Ok(Address { host, port })
+    }
+}
+
+/// Checks for a valid DNS name. One whose last label is all digits, such as `999.1.1.1` or
+/// `192.168.300`, is a mistyped IPv4 address, which resolvers would otherwise read as a short
+/// form of some other address.
+fn is_hostname(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    let numeric = |label: &str| label.chars().all(|c| c.is_ascii_digit());
+    !host.is_empty()
+        && host.len() <= 253
+        && !host.rsplit('.').next().is_some_and(numeric)
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Looks up the index of the network interface an IPv6 zone names, such as `en0` or `2`.
+fn interface_index(zone: &str) -> Option<u32> {
+    if let Ok(index) = zone.parse() {
+        return Some(index);
+    }
+
+    if_addrs::get_if_addrs()
+        .ok()?
+        .into_iter()
+        .find(|interface| interface.name == zone)
+        .and_then(|interface| interface.index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn parse(s: &str) -> Result<Address, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_ipv4_with_and_without_a_port() {
+        let light = parse("192.168.1.40").unwrap();
+        assert_eq!(
+            light.host,
+            Host::Ip(Ipv4Addr::new(192, 168, 1, 40).into(), 0)
+        );
+        assert_eq!(light.port(), PORT);
+        assert_eq!(light.to_string(), "192.168.1.40");
+
+        let light = parse("192.168.1.40:9124").unwrap();
+        assert_eq!(light.port(), 9124);
+        assert_eq!(light.to_string(), "192.168.1.40:9124");
+    }
+
+    #[test]
+    fn parses_ipv6_bare_and_bracketed_with_a_port() {
+        let light = parse("fd00::2").unwrap();
+        assert_eq!(light.host, Host::Ip("fd00::2".parse().unwrap(), 0));
+        assert_eq!(light.port(), PORT);
+
+        let light = parse("[fd00::2]:9124").unwrap();
+        assert_eq!(light.host, Host::Ip("fd00::2".parse().unwrap(), 0));
+        assert_eq!(light.port(), 9124);
+        assert_eq!(light.url_host(), "[fd00::2]");
+        assert_eq!(light.to_string(), "[fd00::2]:9124");
+    }
+
+    #[test]
+    fn parses_an_ipv6_zone() {
+        let light = parse("fe80::1%2").unwrap();
+        assert_eq!(light.host, Host::Ip("fe80::1".parse().unwrap(), 2));
+        assert_eq!(light.port(), PORT);
+
+        let light = parse("[fe80::1%2]:9124").unwrap();
+        assert_eq!(light.host, Host::Ip("fe80::1".parse().unwrap(), 2));
+        assert_eq!(light.port(), 9124);
+        assert_eq!(light.to_string(), "[fe80::1%2]:9124");
+    }
+
+    #[test]
+    fn parses_hostnames_with_and_without_a_port() {
+        let light = parse("Key-Light.local").unwrap();
+        assert_eq!(light.host, Host::Name("key-light.local".to_string()));
+        assert_eq!(light.port(), PORT);
+
+        let light = parse("keylight.local:9124").unwrap();
+        assert_eq!(light.host, Host::Name("keylight.local".to_string()));
+        assert_eq!(light.port(), 9124);
+        assert_eq!(light.to_string(), "keylight.local:9124");
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        for bad in [
+            "",
+            "999.1.1.1",
+            "192.168.300",
+            "192.168.1.40.",
+            "1234",
+            "keylight.local:port",
+            "keylight.local:99999",
+            "[fd00::2",
+            "[fd00::2]9124",
+            "fe80::1%no-such-interface",
+            "-keylight.local",
+            "key_light.local",
+        ] {
+            assert!(parse(bad).is_err(), "{:?} was accepted", bad);
+        }
+    }
+}
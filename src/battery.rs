@@ -0,0 +1,258 @@
+use crate::report::Reading;
+use serde::{Deserialize, Serialize};
+
+/// What battery-powered lights such as the Key Light Mini report at `/elgato/battery-info`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BatteryInfo {
+    pub power_source: u8,
+    pub level: f64,
+    pub status: u8,
+    pub current_battery_voltage: f64,
+    pub input_charge_voltage: f64,
+    pub input_charge_current: f64,
+}
+
+impl BatteryInfo {
+    pub fn power_source_name(&self) -> String {
+        match self.power_source {
+            1 => "mains".to_string(),
+            2 => "battery".to_string(),
+            source => format!("unknown ({})", source),
+        }
+    }
+
+    pub fn status_name(&self) -> String {
+        match self.status {
+            0 => "draining".to_string(),
+            2 => "charging".to_string(),
+            3 => "checking charger".to_string(),
+            status => format!("unknown ({})", status),
+        }
+    }
+}
+
+/// The eco mode settings battery-powered lights keep under `battery` in their settings, as far
+/// as they are shown. They are only read; [`Settings`](crate::settings::Settings) writes them
+/// back as the light sent them.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BatterySettings {
+    #[serde(default)]
+    pub energy_saving: EnergySaving,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EnergySaving {
+    #[serde(default)]
+    pub enable: u8,
+    #[serde(default)]
+    pub minimum_battery_level: f64,
+    #[serde(default)]
+    pub disable_wifi: u8,
+    #[serde(default)]
+    pub adjust_brightness: AdjustBrightness,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AdjustBrightness {
+    #[serde(default)]
+    pub enable: u8,
+    #[serde(default)]
+    pub brightness: f64,
+}
+
+/// A light's battery readings, along with its eco mode settings when it reports them.
+#[derive(Debug, Clone)]
+pub struct Battery {
+    pub info: BatteryInfo,
+    pub settings: Option<BatterySettings>,
+}
+
+#[derive(Serialize)]
+pub struct BatteryJson {
+    level: f64,
+    status: String,
+    power_source: String,
+    voltage: f64,
+    charge_voltage: f64,
+    charge_current: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eco_mode: Option<EcoModeJson>,
+}
+
+#[derive(Serialize)]
+struct EcoModeJson {
+    enabled: bool,
+    minimum_battery_level: f64,
+    disable_wifi: bool,
+    dimmed_brightness: Option<f64>,
+}
+
+impl BatteryJson {
+    pub fn new(battery: &Battery) -> BatteryJson {
+        let info = &battery.info;
+        BatteryJson {
+            level: info.level,
+            status: info.status_name(),
+            power_source: info.power_source_name(),
+            voltage: info.current_battery_voltage,
+            charge_voltage: info.input_charge_voltage,
+            charge_current: info.input_charge_current,
+            eco_mode: battery.settings.as_ref().map(|settings| {
+                let saving = &settings.energy_saving;
+                EcoModeJson {
+                    enabled: saving.enable != 0,
+                    minimum_battery_level: saving.minimum_battery_level,
+                    disable_wifi: saving.disable_wifi != 0,
+                    dimmed_brightness: (saving.adjust_brightness.enable != 0)
+                        .then_some(saving.adjust_brightness.brightness),
+                }
+            }),
+        }
+    }
+}
+
+fn eco_mode(settings: Option<&BatterySettings>) -> String {
+    let Some(settings) = settings else {
+        return "-".to_string();
+    };
+
+    let saving = &settings.energy_saving;
+    if saving.enable == 0 {
+        return "off".to_string();
+    }
+
+    let mut parts = vec![format!("on below {}%", saving.minimum_battery_level)];
+    if saving.adjust_brightness.enable != 0 {
+        parts.push(format!("dims to {}%", saving.adjust_brightness.brightness));
+    }
+    if saving.disable_wifi != 0 {
+        parts.push("turns off Wi-Fi".to_string());
+    }
+    parts.join(", ")
+}
+
+impl Reading for Battery {
+    type Json<'a> = BatteryJson;
+
+    fn lines(&self) -> Vec<String> {
+        let info = &self.info;
+        vec![
+            format!("Level:        {}%", info.level),
+            format!("Status:       {}", info.status_name()),
+            format!("Power source: {}", info.power_source_name()),
+            format!("Voltage:      {:.2} V", info.current_battery_voltage),
+            format!(
+                "Charger:      {:.2} V, {:.2} A",
+                info.input_charge_voltage, info.input_charge_current
+            ),
+            format!("Eco mode:     {}", eco_mode(self.settings.as_ref())),
+        ]
+    }
+
+    fn json(&self) -> BatteryJson {
+        BatteryJson::new(self)
+    }
+
+    fn header() -> String {
+        format!("{:<6} {:<16} {:<8} ECO MODE", "LEVEL", "STATUS", "SOURCE")
+    }
+
+    fn row(&self) -> String {
+        format!(
+            "{:<6} {:<16} {:<8} {}",
+            format!("{}%", self.info.level),
+            self.info.status_name(),
+            self.info.power_source_name(),
+            eco_mode(self.settings.as_ref())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{render, Format, Report};
+    use serde_json::{json, Value};
+
+    fn report(settings: Option<Value>) -> Report<Battery> {
+        Report {
+            name: "mini".to_string(),
+            address: "192.168.0.40".parse().unwrap(),
+            reading: Battery {
+                info: serde_json::from_value(json!({
+                    "powerSource": 2,
+                    "level": 87.5,
+                    "status": 0,
+                    "currentBatteryVoltage": 3.9,
+                    "inputChargeVoltage": 5.1,
+                    "inputChargeCurrent": 1.2,
+                }))
+                .unwrap(),
+                settings: settings.map(|settings| serde_json::from_value(settings).unwrap()),
+            },
+        }
+    }
+
+    fn eco_mode_on() -> Option<Value> {
+        Some(json!({
+            "energySaving": {
+                "enable": 1,
+                "minimumBatteryLevel": 15,
+                "disableWifi": 1,
+                "adjustBrightness": { "enable": 1, "brightness": 10 },
+            },
+        }))
+    }
+
+    #[test]
+    fn json_includes_eco_mode_when_reported() {
+        let rendered: Value =
+            serde_json::from_str(&render(Format::Json, &[report(eco_mode_on())])).unwrap();
+        assert_eq!(
+            rendered,
+            json!([{
+                "name": "mini",
+                "ip_address": "192.168.0.40",
+                "level": 87.5,
+                "status": "draining",
+                "power_source": "battery",
+                "voltage": 3.9,
+                "charge_voltage": 5.1,
+                "charge_current": 1.2,
+                "eco_mode": {
+                    "enabled": true,
+                    "minimum_battery_level": 15.0,
+                    "disable_wifi": true,
+                    "dimmed_brightness": 10.0,
+                },
+            }])
+        );
+
+        let rendered: Value = serde_json::from_str(&render(Format::Json, &[report(None)])).unwrap();
+        assert!(rendered[0].get("eco_mode").is_none());
+    }
+
+    #[test]
+    fn text_describes_eco_mode() {
+        assert_eq!(
+            render(Format::Text, &[report(eco_mode_on())]),
+            "Level:        87.5%\nStatus:       draining\nPower source: battery\nVoltage:      3.90 V\nCharger:      5.10 V, 1.20 A\nEco mode:     on below 15%, dims to 10%, turns off Wi-Fi"
+        );
+        let off = report(Some(json!({ "energySaving": { "enable": 0 } })));
+        assert!(render(Format::Text, &[off]).ends_with("Eco mode:     off"));
+        assert!(render(Format::Text, &[report(None)]).ends_with("Eco mode:     -"));
+    }
+
+    #[test]
+    fn table_has_one_row_per_device() {
+        assert_eq!(
+            render(Format::Table, &[report(None)]),
+            "NAME                     ADDRESS         LEVEL  STATUS           SOURCE   ECO MODE\n\
+             mini                     192.168.0.40    87.5%  draining         battery  -"
+        );
+    }
+}
@@ -0,0 +1,720 @@
+use crate::accessory::AccessoryInfo;
+use crate::address::Address;
+use crate::config::Config;
+use crate::error::Error;
+use crate::keylight::{mired_to_kelvin, Change, Connection, KeyLight, Status};
+use crate::mqtt::{self, Client, Message};
+use crate::units::{Brightness, Kelvin};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// How often the broker hears from us when nothing else is going on. A ping still unanswered
+/// by the next one means the connection is gone.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// How long to wait before connecting to the broker again after losing it.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Where the broker is and how the lights are laid out on it.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub broker: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic_prefix: String,
+    pub discovery_prefix: String,
+    pub poll_interval: Duration,
+    pub connection: Connection,
+}
+
+/// A command published to `<prefix>/<name>/set`, in the JSON schema Home Assistant's MQTT
+/// lights use. Brightness is a percentage and `color_temp` is in Kelvin, as announced.
+#[derive(Deserialize, Debug)]
+struct Command {
+    state: Option<String>,
+    brightness: Option<Brightness>,
+    color_temp: Option<Kelvin>,
+    /// In seconds.
+    transition: Option<f64>,
+}
+
+impl Command {
+    fn parse(payload: &[u8]) -> Result<(Change, Option<Duration>), String> {
+        let command: Command = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+        let on = match command.state.as_deref() {
+            Some("ON") => Some(true),
+            Some("OFF") => Some(false),
+            Some(state) => return Err(format!("unknown state '{}'. Use ON or OFF", state)),
+            None => None,
+        };
+        let transition = command
+            .transition
+            .map(|seconds| {
+                Duration::try_from_secs_f64(seconds)
+                    .map_err(|_| format!("invalid transition of {} seconds", seconds))
+            })
+            .transpose()?;
+
+        let change = Change {
+            on,
+            brightness: command.brightness,
+            temperature: command.color_temp,
+            ..Default::default()
+        };
+        Ok((change, transition.filter(|duration| !duration.is_zero())))
+    }
+}
+
+/// What the bridge asks of a light's worker.
+enum Request {
+    Change(Change, Option<Duration>),
+    /// Read the light now rather than at the next poll.
+    Refresh,
+}
+
+/// What a light's worker read, sent to the bridge after every poll and request.
+struct Reading {
+    index: usize,
+    result: Result<(Status, AccessoryInfo), Error>,
+}
+
+/// Talks to one light on a task of its own, so a slow or unreachable light holds up nothing
+/// but itself while the bridge keeps serving the broker.
+struct Worker {
+    name: String,
+    address: Address,
+    connection: Connection,
+    keylight: Option<KeyLight>,
+    info: Option<AccessoryInfo>,
+}
+
+impl Worker {
+    /// Reads the light every `poll_interval` and after every request, until the bridge goes
+    /// away.
+    async fn run(
+        mut self,
+        index: usize,
+        poll_interval: Duration,
+        mut requests: mpsc::Receiver<Request>,
+        readings: mpsc::Sender<Reading>,
+    ) {
+        let mut poll = tokio::time::interval(poll_interval);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(Request::Change(change, transition)) => {
+                        if let Err(e) = self.change(change, transition).await {
+                            eprintln!("{}: {}", self.name, e);
+                        }
+                    }
+                    Some(Request::Refresh) => {}
+                    None => return,
+                },
+                _ = poll.tick() => {}
+            }
+
+            let result = self.read().await;
+            if result.is_err() {
+                self.keylight = None;
+            }
+            if readings.send(Reading { index, result }).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn change(&mut self, change: Change, transition: Option<Duration>) -> Result<(), Error> {
+        connect(&mut self.keylight, &self.address, self.connection)
+            .await?
+            .change(|_, _| change, transition)
+            .await
+    }
+
+    async fn read(&mut self) -> Result<(Status, AccessoryInfo), Error> {
+        let keylight = connect(&mut self.keylight, &self.address, self.connection).await?;
+        let status = keylight.get().await?;
+        let info = match &self.info {
+            Some(info) => info.clone(),
+            None => self.info.insert(keylight.accessory_info().await?).clone(),
+        };
+        Ok((status, info))
+    }
+}
+
+/// Connects to the light the first time it is needed, and again after it went away.
+async fn connect<'a>(
+    keylight: &'a mut Option<KeyLight>,
+    address: &Address,
+    connection: Connection,
+) -> Result<&'a mut KeyLight, Error> {
+    let connected = match keylight.take() {
+        Some(connected) => connected,
+        None => KeyLight::new(address, connection).await?,
+    };
+    Ok(keylight.insert(connected))
+}
+
+/// One light from the config file, and what was last published about it.
+struct BridgedLight {
+    name: String,
+    requests: mpsc::Sender<Request>,
+    info: Option<AccessoryInfo>,
+    announced: bool,
+    available: Option<bool>,
+    state: Option<String>,
+}
+
+impl BridgedLight {
+    /// Passes a request on to the light's worker without waiting for it, since the worker
+    /// may be busy with a slow light. A refresh that does not fit is not missed, as the worker
+    /// reads the light once it is done anyway.
+    fn request(&self, request: Request) {
+        if let Err(TrySendError::Full(Request::Change(..))) = self.requests.try_send(request) {
+            eprintln!("{}: Still busy, ignoring the command", self.name);
+        }
+    }
+
+    /// Forgets what was published, so it is all published again.
+    fn forget(&mut self) {
+        self.announced = false;
+        self.available = None;
+        self.state = None;
+    }
+
+    /// A stable identifier for Home Assistant: the serial number when the light reports one.
+    fn unique_id(&self) -> String {
+        let id = match &self.info {
+            Some(info) if !info.serial_number.is_empty() => info.serial_number.as_str(),
+            _ => self.name.as_str(),
+        };
+        let id: String = id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("elgato_{}", id)
+    }
+}
+
+struct Bridge {
+    options: Options,
+    lights: Vec<BridgedLight>,
+}
+
+/// Bridges every light in the config file to the MQTT broker until the process is stopped.
+///
+/// Each light listens for commands on `<prefix>/<name>/set`, publishes its state to
+/// `<prefix>/<name>/state` whenever polling finds it changed, and is announced to Home
+/// Assistant under `<discovery prefix>/light/`. A lost broker is reconnected to, but one that
+/// cannot be reached at startup is an error.
+pub async fn run(config: &Config, options: Options) -> Result<(), Error> {
+    let mut names: Vec<&str> = config.lights.keys().map(String::as_str).collect();
+    if names.is_empty() {
+        names.extend(config.ip_address.as_deref());
+    }
+    if names.is_empty() {
+        return Err(Error::Usage(
+            "No lights to bridge. Name them under [lights] in the config file".to_string(),
+        ));
+    }
+
+    let addresses = names
+        .into_iter()
+        .map(|name| {
+            let address = Address::from_str(config.address(name)).map_err(|e| {
+                Error::InvalidAddress(format!("Invalid address for {}: {}", name, e))
+            })?;
+            Ok((name.to_string(), address))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let (readings_sender, mut readings) = mpsc::channel(64);
+    let lights = addresses
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, address))| {
+            let (requests, receiver) = mpsc::channel(8);
+            let worker = Worker {
+                name: name.clone(),
+                address,
+                connection: options.connection,
+                keylight: None,
+                info: None,
+            };
+            tokio::spawn(worker.run(
+                index,
+                options.poll_interval,
+                receiver,
+                readings_sender.clone(),
+            ));
+            BridgedLight {
+                name,
+                requests,
+                info: None,
+                announced: false,
+                available: None,
+                state: None,
+            }
+        })
+        .collect();
+
+    let broker = if options.broker.contains(':') {
+        options.broker.clone()
+    } else {
+        format!("{}:{}", options.broker, mqtt::PORT)
+    };
+    let mut bridge = Bridge { options, lights };
+    let client_options = mqtt::Options {
+        client_id: format!("elgato-light-{}", std::process::id()),
+        username: bridge.options.username.clone(),
+        password: bridge.options.password.clone(),
+        keep_alive: KEEP_ALIVE * 2,
+        will: Some(bridge.availability(&bridge.bridge_topic(), false)),
+    };
+
+    let mut connected = false;
+    loop {
+        let timeout = bridge.options.connection.timeout;
+        let error = match Client::connect(&broker, &client_options, timeout).await {
+            Ok((client, messages)) => {
+                connected = true;
+                eprintln!("Connected to {}", broker);
+                match bridge.session(client, messages, &mut readings).await {
+                    Ok(never) => match never {},
+                    Err(e) => e,
+                }
+            }
+            Err(e) if !connected => return Err(e),
+            Err(e) => e,
+        };
+
+        eprintln!(
+            "{}. Reconnecting in {}",
+            error,
+            humantime::format_duration(RECONNECT_DELAY)
+        );
+        for light in &mut bridge.lights {
+            light.forget();
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+impl Bridge {
+    /// Serves one connection to the broker until it is lost.
+    async fn session(
+        &mut self,
+        mut client: Client,
+        mut messages: mpsc::Receiver<Result<Message, Error>>,
+        readings: &mut mpsc::Receiver<Reading>,
+    ) -> Result<Infallible, Error> {
+        client
+            .publish(&self.availability(&self.bridge_topic(), true))
+            .await?;
+        client
+            .subscribe(&format!("{}/+/set", self.options.topic_prefix))
+            .await?;
+        client
+            .subscribe(&format!("{}/status", self.options.discovery_prefix))
+            .await?;
+        for light in &self.lights {
+            light.request(Request::Refresh);
+        }
+
+        let mut ping = tokio::time::interval(KEEP_ALIVE);
+        // The first tick completes immediately; the connection is fresh, so skip it.
+        ping.tick().await;
+
+        loop {
+            tokio::select! {
+                message = messages.recv() => match message {
+                    Some(Ok(message)) => self.handle(message),
+                    Some(Err(e)) => return Err(e),
+                    None => return Err(Error::Mqtt("the connection to the broker closed".to_string())),
+                },
+                Some(reading) = readings.recv() => self.publish_reading(&mut client, reading).await?,
+                _ = ping.tick() => client.ping().await?,
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) {
+        // Home Assistant announces itself when it starts, and has forgotten every light that
+        // was not published with a retained config.
+        if message.topic == format!("{}/status", self.options.discovery_prefix) {
+            if message.payload == b"online" {
+                for light in &mut self.lights {
+                    light.forget();
+                    light.request(Request::Refresh);
+                }
+            }
+            return;
+        }
+
+        let name = message
+            .topic
+            .strip_prefix(&format!("{}/", self.options.topic_prefix))
+            .and_then(|rest| rest.strip_suffix("/set"));
+        let Some(light) = name.and_then(|name| self.lights.iter().find(|l| l.name == name)) else {
+            return;
+        };
+
+        match Command::parse(&message.payload) {
+            Ok((change, transition)) => light.request(Request::Change(change, transition)),
+            Err(e) => eprintln!("{}: Ignoring the command: {}", light.name, e),
+        }
+    }
+
+    /// Publishes whatever changed since the last reading of a light: its availability, its
+    /// state, and its Home Assistant config the first time it is reached.
+    async fn publish_reading(
+        &mut self,
+        client: &mut Client,
+        reading: Reading,
+    ) -> Result<(), Error> {
+        let index = reading.index;
+        let availability_topic = self.light_topic(index, "availability");
+        let light = &mut self.lights[index];
+        let status = match reading.result {
+            Ok((status, info)) => {
+                light.info = Some(info);
+                status
+            }
+            Err(e) => {
+                if light.available != Some(false) {
+                    eprintln!("{}: {}", light.name, e);
+                    light.available = Some(false);
+                    client
+                        .publish(&self.availability(&availability_topic, false))
+                        .await?;
+                }
+                return Ok(());
+            }
+        };
+
+        if !self.lights[index].announced {
+            client.publish(&self.discovery(index)).await?;
+            self.lights[index].announced = true;
+        }
+        if self.lights[index].available != Some(true) {
+            self.lights[index].available = Some(true);
+            client
+                .publish(&self.availability(&availability_topic, true))
+                .await?;
+        }
+
+        let state = state_json(&status);
+        if self.lights[index].state.as_ref() != Some(&state) {
+            client
+                .publish(&Message {
+                    topic: self.light_topic(index, "state"),
+                    payload: state.clone().into_bytes(),
+                    retain: true,
+                })
+                .await?;
+            self.lights[index].state = Some(state);
+        }
+        Ok(())
+    }
+
+    fn bridge_topic(&self) -> String {
+        format!("{}/bridge/availability", self.options.topic_prefix)
+    }
+
+    fn light_topic(&self, index: usize, topic: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.options.topic_prefix, self.lights[index].name, topic
+        )
+    }
+
+    fn availability(&self, topic: &str, online: bool) -> Message {
+        Message {
+            topic: topic.to_string(),
+            payload: if online { "online" } else { "offline" }.into(),
+            retain: true,
+        }
+    }
+
+    /// The Home Assistant MQTT discovery config that makes the light show up as a `light`
+    /// entity with brightness and color temperature.
+    fn discovery(&self, index: usize) -> Message {
+        let light = &self.lights[index];
+        let unique_id = light.unique_id();
+        let info = light.info.clone().unwrap_or_default();
+        let device_name = match info.display_name.as_str() {
+            "" => light.name.as_str(),
+            display_name => display_name,
+        };
+
+        let config = serde_json::json!({
+            "name": null,
+            "unique_id": unique_id,
+            "schema": "json",
+            "command_topic": self.light_topic(index, "set"),
+            "state_topic": self.light_topic(index, "state"),
+            "availability": [
+                { "topic": self.bridge_topic() },
+                { "topic": self.light_topic(index, "availability") },
+            ],
+            "availability_mode": "all",
+            "brightness": true,
+            "brightness_scale": 100,
+            "supported_color_modes": ["color_temp"],
+            "color_temp_kelvin": true,
+            "min_kelvin": Kelvin::MIN.get(),
+            "max_kelvin": Kelvin::MAX.get(),
+            "device": {
+                "identifiers": [unique_id],
+                "name": device_name,
+                "manufacturer": "Elgato",
+                "model": info.product_name,
+                "sw_version": info.firmware_version,
+            },
+        });
+
+        Message {
+            topic: format!(
+                "{}/light/{}/config",
+                self.options.discovery_prefix, unique_id
+            ),
+            payload: config.to_string().into_bytes(),
+            retain: true,
+        }
+    }
+}
+
+/// The state Home Assistant expects on the state topic. Each device is bridged as a single
+/// Home Assistant light, so a device with several lights is on when any of them is and
+/// deliberately reports only the look of its first; commands still change every light.
+fn state_json(status: &Status) -> String {
+    let on = status.lights.iter().any(|light| light.on != 0);
+    let mut state = serde_json::json!({
+        "state": if on { "ON" } else { "OFF" },
+        "color_mode": "color_temp",
+    });
+    if let Some(light) = status.lights.first() {
+        state["brightness"] = light.brightness.into();
+        if light.hue.is_none() {
+            state["color_temp"] = mired_to_kelvin(light.temperature).into();
+        }
+    }
+    state.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mqtt::{parse_publish, put_length, put_string, read_packet};
+    use crate::testing::fake_light;
+    use serde_json::{json, Value};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn command_reads_home_assistant_json() {
+        let (change, transition) =
+            Command::parse(br#"{"state":"ON","brightness":40,"color_temp":4500,"transition":1.5}"#)
+                .unwrap();
+        assert_eq!(
+            change,
+            Change {
+                on: Some(true),
+                brightness: Some("40".parse().unwrap()),
+                temperature: Some("4500".parse().unwrap()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(transition, Some(Duration::from_millis(1500)));
+
+        let (change, transition) = Command::parse(br#"{"state":"OFF"}"#).unwrap();
+        assert_eq!(
+            change,
+            Change {
+                on: Some(false),
+                ..Default::default()
+            }
+        );
+        assert_eq!(transition, None);
+    }
+
+    #[test]
+    fn command_drops_a_zero_transition() {
+        let (_, transition) = Command::parse(br#"{"state":"ON","transition":0}"#).unwrap();
+        assert_eq!(transition, None);
+    }
+
+    #[test]
+    fn command_rejects_what_the_light_cannot_do() {
+        for bad in [
+            &br#"{"state":"DIM"}"#[..],
+            br#"{"brightness":101}"#,
+            br#"{"color_temp":2000}"#,
+            br#"{"transition":-1}"#,
+            b"ON",
+        ] {
+            assert!(
+                Command::parse(bad).is_err(),
+                "{} was accepted",
+                String::from_utf8_lossy(bad)
+            );
+        }
+    }
+
+    /// Reads from the bridge until a packet `until` accepts, returning everything read.
+    async fn read_until(
+        stream: &mut TcpStream,
+        until: impl Fn(u8, &[u8]) -> bool,
+    ) -> Vec<(u8, Vec<u8>)> {
+        let mut packets = Vec::new();
+        loop {
+            let (header, body) = tokio::time::timeout(Duration::from_secs(10), read_packet(stream))
+                .await
+                .expect("the bridge kept publishing")
+                .unwrap();
+            let done = until(header, &body);
+            packets.push((header, body));
+            if done {
+                return packets;
+            }
+        }
+    }
+
+    fn publishes(packets: &[(u8, Vec<u8>)]) -> Vec<Message> {
+        packets
+            .iter()
+            .filter(|(header, _)| header & 0xf0 == 0x30)
+            .map(|(header, body)| parse_publish(*header, body).unwrap())
+            .collect()
+    }
+
+    fn published<'a>(messages: &'a [Message], topic: &str) -> &'a Message {
+        messages
+            .iter()
+            .find(|message| message.topic == topic)
+            .unwrap_or_else(|| panic!("nothing was published to {}", topic))
+    }
+
+    fn payload(message: &Message) -> Value {
+        serde_json::from_slice(&message.payload).unwrap()
+    }
+
+    /// The body of a SUBSCRIBE to `filter` at QoS 0, after its packet identifier.
+    fn subscription(filter: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_string(&mut body, filter);
+        body.push(0);
+        body
+    }
+
+    fn is_state(header: u8, body: &[u8]) -> bool {
+        header & 0xf0 == 0x30
+            && parse_publish(header, body).is_some_and(|m| m.topic == "elgato/desk/state")
+    }
+
+    #[tokio::test]
+    async fn bridges_a_light_to_a_broker() {
+        let light = fake_light().await;
+        let broker = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Config {
+            lights: [("desk".to_string(), light.to_string())].into(),
+            ..Default::default()
+        };
+        let options = Options {
+            broker: broker.local_addr().unwrap().to_string(),
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            topic_prefix: "elgato".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+            poll_interval: Duration::from_secs(60),
+            connection: Connection {
+                timeout: Duration::from_secs(2),
+                retries: 0,
+            },
+        };
+        let bridge = tokio::spawn(async move { run(&config, options).await });
+
+        let (mut stream, _) = broker.accept().await.unwrap();
+        let (header, connect) = read_packet(&mut stream).await.unwrap();
+        assert_eq!(header, 0x10);
+        assert_eq!(&connect[..7], b"\x00\x04MQTT\x04");
+        // Username, password, a retained will, and a clean session.
+        assert_eq!(connect[7], 0xe6);
+        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).await.unwrap();
+
+        let packets = read_until(&mut stream, is_state).await;
+        let subscriptions: Vec<&[u8]> = packets
+            .iter()
+            .filter(|(header, _)| *header == 0x82)
+            .map(|(_, body)| &body[2..])
+            .collect();
+        assert_eq!(
+            subscriptions,
+            [
+                subscription("elgato/+/set"),
+                subscription("homeassistant/status")
+            ]
+        );
+
+        let messages = publishes(&packets);
+        assert!(messages.iter().all(|message| message.retain));
+        assert_eq!(
+            published(&messages, "elgato/bridge/availability").payload,
+            b"online"
+        );
+        assert_eq!(
+            published(&messages, "elgato/desk/availability").payload,
+            b"online"
+        );
+
+        let discovery = payload(published(
+            &messages,
+            "homeassistant/light/elgato_ab12/config",
+        ));
+        assert_eq!(discovery["unique_id"], "elgato_ab12");
+        assert_eq!(discovery["command_topic"], "elgato/desk/set");
+        assert_eq!(discovery["state_topic"], "elgato/desk/state");
+        assert_eq!(discovery["device"]["name"], "Desk");
+
+        assert_eq!(
+            payload(published(&messages, "elgato/desk/state")),
+            json!({
+                "state": "ON",
+                "color_mode": "color_temp",
+                "brightness": 35,
+                "color_temp": 4500,
+            })
+        );
+
+        let mut command = Vec::new();
+        put_string(&mut command, "elgato/desk/set");
+        command.extend_from_slice(br#"{"state":"OFF","brightness":60}"#);
+        let mut packet = vec![0x30];
+        put_length(&mut packet, command.len());
+        packet.extend_from_slice(&command);
+        stream.write_all(&packet).await.unwrap();
+
+        let packets = read_until(&mut stream, is_state).await;
+        assert_eq!(
+            payload(published(&publishes(&packets), "elgato/desk/state")),
+            json!({
+                "state": "OFF",
+                "color_mode": "color_temp",
+                "brightness": 60,
+                "color_temp": 4500,
+            })
+        );
+
+        bridge.abort();
+    }
+}
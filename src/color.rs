@@ -0,0 +1,272 @@
+use crate::units::{Brightness, Hue, Saturation};
+use std::str::FromStr;
+
+/// A color in the hue, saturation, and brightness model that color lights such as the Light
+/// Strip use, read from `#RRGGBB` or a CSS color name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub hue: Hue,
+    pub saturation: Saturation,
+    pub brightness: Brightness,
+}
+
+impl Color {
+    /// Converts 8-bit red, green, and blue to HSB, where brightness is the strongest of the
+    /// three channels.
+    pub fn from_rgb(red: u8, green: u8, blue: u8) -> Color {
+        let (r, g, b) = (
+            f64::from(red) / 255.0,
+            f64::from(green) / 255.0,
+            f64::from(blue) / 255.0,
+        );
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+        Color {
+            hue: Hue::clamped(hue.round() as u16 % 360),
+            saturation: Saturation::clamped((saturation * 100.0).round() as u8),
+            brightness: Brightness::clamped((max * 100.0).round() as u8),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rgb = match s.strip_prefix('#') {
+            Some(hex) => (hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+                .then(|| u32::from_str_radix(hex, 16).ok())
+                .flatten()
+                .ok_or_else(|| format!("'{}' is not a color. Use #RRGGBB, such as #ff8800", s))?,
+            None => {
+                let name = s.to_ascii_lowercase();
+                CSS_COLORS
+                    .iter()
+                    .find(|(css_name, _)| *css_name == name)
+                    .map(|(_, rgb)| *rgb)
+                    .ok_or_else(|| {
+                        format!(
+                            "Unknown color '{}'. Use #RRGGBB or a CSS color name such as orange",
+                            s
+                        )
+                    })?
+            }
+        };
+
+        Ok(Color::from_rgb(
+            (rgb >> 16) as u8,
+            (rgb >> 8) as u8,
+            rgb as u8,
+        ))
+    }
+}
+
+/// The named colors of CSS Color Module Level 4.
+const CSS_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hsb(color: Color) -> (u16, u8, u8) {
+        (
+            color.hue.get(),
+            color.saturation.get(),
+            color.brightness.get(),
+        )
+    }
+
+    #[test]
+    fn primaries_convert_to_their_hues() {
+        assert_eq!(hsb(Color::from_rgb(255, 0, 0)), (0, 100, 100));
+        assert_eq!(hsb(Color::from_rgb(0, 255, 0)), (120, 100, 100));
+        assert_eq!(hsb(Color::from_rgb(0, 0, 255)), (240, 100, 100));
+        assert_eq!(hsb(Color::from_rgb(255, 255, 0)), (60, 100, 100));
+        assert_eq!(hsb(Color::from_rgb(255, 0, 255)), (300, 100, 100));
+    }
+
+    #[test]
+    fn grey_and_black_have_no_hue_or_saturation() {
+        assert_eq!(hsb(Color::from_rgb(128, 128, 128)), (0, 0, 50));
+        assert_eq!(hsb(Color::from_rgb(255, 255, 255)), (0, 0, 100));
+        assert_eq!(hsb(Color::from_rgb(0, 0, 0)), (0, 0, 0));
+    }
+
+    #[test]
+    fn parses_hex_and_css_names() {
+        assert_eq!("#ff8800".parse(), Ok(Color::from_rgb(0xff, 0x88, 0x00)));
+        assert_eq!("#FF8800".parse(), Ok(Color::from_rgb(0xff, 0x88, 0x00)));
+        assert_eq!("Orange".parse(), Ok(Color::from_rgb(0xff, 0xa5, 0x00)));
+    }
+
+    #[test]
+    fn rejects_malformed_hex() {
+        for bad in [
+            "#+fffff", "#-fffff", "#fff", "#ff88001", "#gg8800", "ff8800", "#",
+        ] {
+            assert!(bad.parse::<Color>().is_err(), "{:?} was accepted", bad);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::error::Error;
+use crate::units::{Brightness, Kelvin};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const DEFAULT_BRIGHTNESS: Brightness = Brightness::clamped(10);
+pub const DEFAULT_TEMPERATURE: Kelvin = Kelvin::clamped(3000);
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_RETRIES: u32 = 2;
+pub const DEFAULT_DISCOVER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Settings read from `$XDG_CONFIG_HOME/elgato-light/config.toml`, or the file named by
+/// `ELGATO_LIGHT_CONFIG`. Every key is optional; command line flags and environment
+/// variables take precedence over anything set here.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub ip_address: Option<String>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub discover_timeout: Option<Duration>,
+    pub on: OnConfig,
+    /// Applied when `toggle` turns a light on. Unset values keep the light's previous look.
+    pub toggle: OnConfig,
+    pub lights: BTreeMap<String, String>,
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OnConfig {
+    pub brightness: Option<Brightness>,
+    pub temperature: Option<Kelvin>,
+}
+
+impl Config {
+    /// Reads the config file, with `ELGATO_LIGHT_IP_ADDRESS` taking the place of `ip_address`
+    /// when set, so every command falls back to the same light.
+    pub fn load() -> Result<Config, Error> {
+        let mut config = Config::read()?;
+        if let Some(ip_address) = env::var("ELGATO_LIGHT_IP_ADDRESS")
+            .ok()
+            .filter(|ip_address| !ip_address.is_empty())
+        {
+            config.ip_address = Some(ip_address);
+        }
+        Ok(config)
+    }
+
+    fn read() -> Result<Config, Error> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| {
+                Error::Config(format!("Invalid config file {}: {}", path.display(), e))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(Error::Config(format!(
+                "Unable to read config file {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("ELGATO_LIGHT_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+
+        let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+
+        Some(config_dir.join("elgato-light").join("config.toml"))
+    }
+
+    pub fn light(&self, name: &str) -> Result<&str, Error> {
+        if let Some(ip_address) = self.lights.get(name) {
+            return Ok(ip_address);
+        }
+
+        if self.lights.is_empty() {
+            return Err(Error::Config(format!(
+                "Unknown light '{}'. No lights are named in the config file",
+                name
+            )));
+        }
+
+        let known: Vec<&str> = self.lights.keys().map(String::as_str).collect();
+        Err(Error::Config(format!(
+            "Unknown light '{}'. Known lights: {}",
+            name,
+            known.join(", ")
+        )))
+    }
+
+    /// Looks up the address of a named light, treating anything that is not a name as an
+    /// address already.
+    pub fn address<'a>(&'a self, light: &'a str) -> &'a str {
+        self.lights.get(light).map(String::as_str).unwrap_or(light)
+    }
+
+    pub fn group(&self, name: &str) -> Result<&[String], Error> {
+        match self.groups.get(name) {
+            Some(members) if members.is_empty() => {
+                Err(Error::Config(format!("Group '{}' has no lights", name)))
+            }
+            Some(members) => Ok(members),
+            None => Err(Error::Config(format!("Unknown group '{}'", name))),
+        }
+    }
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    humantime::parse_duration(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_documented_keys() {
+        let config: Config = toml::from_str(
+            r#"
+            ip_address = "192.168.0.25"
+            timeout = "5s"
+            retries = 2
+            discover_timeout = "1500ms"
+
+            [on]
+            brightness = 10
+            temperature = 3000
+
+            [toggle]
+            brightness = 40
+
+            [lights]
+            desk = "192.168.0.25"
+            shelf = "keylight.local:9124"
+
+            [groups]
+            office = ["desk", "shelf"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.ip_address.as_deref(), Some("192.168.0.25"));
+        assert_eq!(config.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.retries, Some(2));
+        assert_eq!(config.discover_timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(config.on.brightness, Some(Brightness::clamped(10)));
+        assert_eq!(config.on.temperature, Some(Kelvin::clamped(3000)));
+        assert_eq!(config.toggle.brightness, Some(Brightness::clamped(40)));
+        assert_eq!(config.toggle.temperature, None);
+        assert_eq!(config.light("shelf").unwrap(), "keylight.local:9124");
+        assert_eq!(config.group("office").unwrap(), ["desk", "shelf"]);
+    }
+
+    #[test]
+    fn every_key_is_optional() {
+        let config: Config = toml::from_str("").unwrap();
+        assert!(config.ip_address.is_none());
+        assert!(config.timeout.is_none());
+        assert!(config.lights.is_empty());
+        assert!(config.groups.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_bad_values() {
+        for bad in [
+            "ip_adress = \"192.168.0.25\"",
+            "timeout = \"soon\"",
+            "timeout = 5",
+            "[on]\nbrightness = 101",
+            "[on]\ntemperature = 2000",
+            "[on]\ncolor = \"red\"",
+            "[groups]\noffice = \"desk\"",
+        ] {
+            assert!(
+                toml::from_str::<Config>(bad).is_err(),
+                "{:?} was accepted",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn environment_ip_address_wins_over_the_config_file() {
+        let dir = env::temp_dir().join(format!("elgato-light-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "ip_address = \"192.168.0.25\"").unwrap();
+        env::set_var("ELGATO_LIGHT_CONFIG", &path);
+
+        env::remove_var("ELGATO_LIGHT_IP_ADDRESS");
+        let from_file = Config::load().unwrap().ip_address;
+        env::set_var("ELGATO_LIGHT_IP_ADDRESS", "192.168.0.30");
+        let from_env = Config::load().unwrap().ip_address;
+        env::remove_var("ELGATO_LIGHT_IP_ADDRESS");
+        env::remove_var("ELGATO_LIGHT_CONFIG");
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(from_file.as_deref(), Some("192.168.0.25"));
+        assert_eq!(from_env.as_deref(), Some("192.168.0.30"));
+    }
+
+    #[test]
+    fn names_resolve_to_addresses() {
+        let config: Config =
+            toml::from_str("[lights]\ndesk = \"192.168.0.25\"\n[groups]\nempty = []").unwrap();
+        assert_eq!(config.address("desk"), "192.168.0.25");
+        assert_eq!(config.address("192.168.0.30"), "192.168.0.30");
+        assert!(matches!(config.light("shelf"), Err(Error::Config(_))));
+        assert!(matches!(config.group("empty"), Err(Error::Config(_))));
+        assert!(matches!(config.group("office"), Err(Error::Config(_))));
+    }
+}
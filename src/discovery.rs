@@ -0,0 +1,170 @@
+use crate::address::Address;
+use crate::error::Error;
+use crate::keylight::{Connection, KeyLight};
+use mdns_sd::{HostnameResolutionEvent, ScopedIp, ServiceDaemon, ServiceEvent};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV6};
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const SERVICE_TYPE: &str = "_elg._tcp.local.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredLight {
+    pub name: String,
+    pub ip_address: Ipv4Addr,
+    pub port: u16,
+    pub serial_number: Option<String>,
+}
+
+/// Browses the local network for Elgato lights for `timeout`, then asks each one for its
+/// serial number.
+pub async fn discover(
+    timeout: Duration,
+    connection: Connection,
+) -> Result<Vec<DiscoveredLight>, Error> {
+    let daemon = ServiceDaemon::new()?;
+    let mut lights = browse(&daemon, SERVICE_TYPE, timeout).await?;
+    let _ = daemon.shutdown();
+
+    for light in lights.iter_mut() {
+        let address = Address::from(SocketAddr::new(light.ip_address.into(), light.port));
+        let info = async {
+            KeyLight::new(&address, connection)
+                .await?
+                .accessory_info()
+                .await
+        };
+        light.serial_number = info.await.ok().map(|info| info.serial_number);
+    }
+
+    Ok(lights)
+}
+
+/// Collects every instance of `service_type` announced on `daemon` until `timeout` elapses.
+///
+/// Taking the daemon and service type as arguments lets a stand-in responder registered on
+/// another `ServiceDaemon` be browsed without any real hardware on the network.
+pub async fn browse(
+    daemon: &ServiceDaemon,
+    service_type: &str,
+    timeout: Duration,
+) -> Result<Vec<DiscoveredLight>, Error> {
+    let receiver = daemon.browse(service_type)?;
+    let deadline = Instant::now() + timeout;
+    let mut lights: Vec<DiscoveredLight> = Vec::new();
+
+    while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, receiver.recv_async()).await {
+        let ServiceEvent::ServiceResolved(service) = event else {
+            continue;
+        };
+        let Some(ip_address) = service.get_addresses_v4().into_iter().min() else {
+            continue;
+        };
+
+        let name = service
+            .get_fullname()
+            .trim_end_matches(service_type)
+            .trim_end_matches('.')
+            .to_string();
+
+        if lights.iter().any(|light| light.name == name) {
+            continue;
+        }
+
+        lights.push(DiscoveredLight {
+            name,
+            ip_address,
+            port: service.get_port(),
+            serial_number: None,
+        });
+    }
+
+    let _ = daemon.stop_browse(service_type);
+    lights.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(lights)
+}
+
+/// Asks the local network for the address of a `.local` hostname, preferring IPv4 when the
+/// light answers with both.
+pub async fn resolve_hostname(
+    hostname: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<Option<SocketAddr>, Error> {
+    let daemon = ServiceDaemon::new()?;
+    let receiver = daemon.resolve_hostname(&format!("{}.", hostname), None)?;
+    let deadline = Instant::now() + timeout;
+    let mut found = None;
+
+    while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, receiver.recv_async()).await {
+        let HostnameResolutionEvent::AddressesFound(_, addresses) = event else {
+            continue;
+        };
+        let mut addresses: Vec<SocketAddr> = addresses
+            .iter()
+            .filter_map(|address| match address {
+                ScopedIp::V4(v4) => Some(SocketAddr::new((*v4.addr()).into(), port)),
+                ScopedIp::V6(v6) => {
+                    Some(SocketAddrV6::new(*v6.addr(), port, 0, v6.scope_id().index).into())
+                }
+                _ => None,
+            })
+            .collect();
+        addresses.sort_by_key(SocketAddr::is_ipv6);
+        found = addresses.into_iter().next();
+        if found.is_some() {
+            break;
+        }
+    }
+
+    let _ = daemon.shutdown();
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mdns_sd::{IfKind, ServiceInfo};
+
+    /// A daemon that only talks over IPv4 loopback, so the test needs no network.
+    fn loopback_daemon() -> ServiceDaemon {
+        let daemon = ServiceDaemon::new().expect("daemon starts");
+        daemon.disable_interface(IfKind::All).unwrap();
+        daemon.enable_interface(IfKind::LoopbackV4).unwrap();
+        daemon
+    }
+
+    #[tokio::test]
+    async fn browse_finds_a_stand_in_responder() {
+        let service_type = format!("_elg-test-{}._tcp.local.", std::process::id() % 100_000);
+        let responder = loopback_daemon();
+        let service = ServiceInfo::new(
+            &service_type,
+            "Elgato Key Light Test",
+            "elgato-key-light-test.local.",
+            "127.0.0.1",
+            9123,
+            None,
+        )
+        .unwrap();
+        responder.register(service).unwrap();
+
+        let daemon = loopback_daemon();
+        let lights = browse(&daemon, &service_type, Duration::from_secs(3))
+            .await
+            .unwrap();
+        let _ = daemon.shutdown();
+        let _ = responder.shutdown();
+
+        assert_eq!(
+            lights,
+            vec![DiscoveredLight {
+                name: "Elgato Key Light Test".to_string(),
+                ip_address: Ipv4Addr::LOCALHOST,
+                port: 9123,
+                serial_number: None,
+            }]
+        );
+    }
+}
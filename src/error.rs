@@ -0,0 +1,120 @@
+use thiserror::Error;
+
+/// Everything that can go wrong, each kind with its own process exit code so that scripts can
+/// tell a light that is offline apart from a mistyped command.
+///
+/// | Code | Meaning |
+/// |------|---------|
+/// | 0 | Success |
+/// | 1 | Any other failure, such as mDNS discovery being unavailable, `serve` failing to listen, or the MQTT broker refusing `mqtt` |
+/// | 2 | Invalid arguments |
+/// | 3 | Invalid or unreadable config or scenes file, or an unknown light, group, or scene name |
+/// | 4 | Invalid light address |
+/// | 5 | Light unreachable |
+/// | 6 | Timed out waiting for the light |
+/// | 7 | The light responded with an HTTP error |
+/// | 8 | The light's response could not be understood |
+/// | 9 | Several lights failed for different reasons |
+/// | 10 | The light does not support the command, such as `battery` on a light without one |
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Usage(String),
+
+    #[error("{0}")]
+    Config(String),
+
+    #[error("{0}")]
+    InvalidAddress(String),
+
+    #[error("Unable to reach the light at {0}")]
+    Unreachable(String),
+
+    #[error("Timed out waiting for the light at {0}")]
+    Timeout(String),
+
+    #[error("The light at {address} returned an error: {status}")]
+    Http {
+        address: String,
+        status: reqwest::StatusCode,
+    },
+
+    #[error("The light at {0} sent a response that could not be understood")]
+    MalformedResponse(String),
+
+    #[error("{0}")]
+    Unsupported(String),
+
+    #[error("mDNS discovery failed: {0}")]
+    Discovery(String),
+
+    #[error("HTTP server failed: {0}")]
+    Server(String),
+
+    #[error("MQTT broker error: {0}")]
+    Mqtt(String),
+
+    #[error("{failed} of {total} lights failed")]
+    LightsFailed {
+        failed: usize,
+        total: usize,
+        exit_code: i32,
+    },
+}
+
+impl Error {
+    pub const USAGE_EXIT_CODE: i32 = 2;
+    const MIXED_FAILURES_EXIT_CODE: i32 = 9;
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Discovery(_) | Error::Server(_) | Error::Mqtt(_) => 1,
+            Error::Usage(_) => Error::USAGE_EXIT_CODE,
+            Error::Config(_) => 3,
+            Error::InvalidAddress(_) => 4,
+            Error::Unreachable(_) => 5,
+            Error::Timeout(_) => 6,
+            Error::Http { .. } => 7,
+            Error::MalformedResponse(_) => 8,
+            Error::Unsupported(_) => 10,
+            Error::LightsFailed { exit_code, .. } => *exit_code,
+        }
+    }
+
+    /// Summarises the failures of several lights, keeping their exit code when they all
+    /// failed the same way.
+    pub fn lights_failed<'a>(errors: impl IntoIterator<Item = &'a Error>, total: usize) -> Error {
+        let codes: Vec<i32> = errors.into_iter().map(Error::exit_code).collect();
+        let exit_code = match codes.first() {
+            Some(code) if codes.iter().all(|c| c == code) => *code,
+            _ => Error::MIXED_FAILURES_EXIT_CODE,
+        };
+
+        Error::LightsFailed {
+            failed: codes.len(),
+            total,
+            exit_code,
+        }
+    }
+
+    /// Classifies a failed request to the light at `address`.
+    pub fn request(address: impl ToString, e: reqwest::Error) -> Error {
+        let address = address.to_string();
+        if e.is_timeout() {
+            Error::Timeout(address)
+        } else if e.is_decode() {
+            Error::MalformedResponse(address)
+        } else if let Some(status) = e.status() {
+            Error::Http { address, status }
+        } else {
+            // Refused, reset, or otherwise failed before the light answered.
+            Error::Unreachable(address)
+        }
+    }
+}
+
+impl From<mdns_sd::Error> for Error {
+    fn from(e: mdns_sd::Error) -> Self {
+        Error::Discovery(e.to_string())
+    }
+}
@@ -0,0 +1,138 @@
+use crate::accessory::AccessoryInfo;
+use crate::report::Reading;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct InfoJson<'a> {
+    product_name: &'a str,
+    hardware_board_type: u32,
+    firmware_version: &'a str,
+    firmware_build_number: u32,
+    serial_number: &'a str,
+    display_name: &'a str,
+    features: &'a [String],
+}
+
+impl Reading for AccessoryInfo {
+    type Json<'a> = InfoJson<'a>;
+
+    fn lines(&self) -> Vec<String> {
+        vec![
+            format!("Product:        {}", self.product_name),
+            format!("Display name:   {}", self.display_name),
+            format!("Serial number:  {}", self.serial_number),
+            format!("Hardware board: {}", self.hardware_board_type),
+            format!(
+                "Firmware:       {} (build {})",
+                self.firmware_version, self.firmware_build_number
+            ),
+            format!("Features:       {}", self.features.join(", ")),
+        ]
+    }
+
+    fn json(&self) -> InfoJson<'_> {
+        InfoJson {
+            product_name: &self.product_name,
+            hardware_board_type: self.hardware_board_type,
+            firmware_version: &self.firmware_version,
+            firmware_build_number: self.firmware_build_number,
+            serial_number: &self.serial_number,
+            display_name: &self.display_name,
+            features: &self.features,
+        }
+    }
+
+    fn header() -> String {
+        format!("{:<24} {:<10} {:<6} SERIAL", "PRODUCT", "FIRMWARE", "BUILD")
+    }
+
+    fn row(&self) -> String {
+        format!(
+            "{:<24} {:<10} {:<6} {}",
+            self.product_name,
+            self.firmware_version,
+            self.firmware_build_number,
+            self.serial_number
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{render, Format, Report};
+    use serde_json::{json, Value};
+
+    fn report(name: &str, address: &str, serial_number: &str) -> Report<AccessoryInfo> {
+        Report {
+            name: name.to_string(),
+            address: address.parse().unwrap(),
+            reading: serde_json::from_value(json!({
+                "productName": "Elgato Key Light",
+                "hardwareBoardType": 53,
+                "firmwareBuildNumber": 218,
+                "firmwareVersion": "1.0.3",
+                "serialNumber": serial_number,
+                "displayName": "Desk",
+                "features": ["lights"],
+            }))
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn json_lists_what_the_device_reports() {
+        let rendered: Value = serde_json::from_str(&render(
+            Format::Json,
+            &[report("desk", "192.168.0.25", "BW33J1A02345")],
+        ))
+        .unwrap();
+        assert_eq!(
+            rendered,
+            json!([{
+                "name": "desk",
+                "ip_address": "192.168.0.25",
+                "product_name": "Elgato Key Light",
+                "hardware_board_type": 53,
+                "firmware_version": "1.0.3",
+                "firmware_build_number": 218,
+                "serial_number": "BW33J1A02345",
+                "display_name": "Desk",
+                "features": ["lights"],
+            }])
+        );
+        assert_eq!(render(Format::Json, &[] as &[Report<AccessoryInfo>]), "[]");
+    }
+
+    #[test]
+    fn text_is_labelled_only_for_several_devices() {
+        let desk = "Product:        Elgato Key Light\nDisplay name:   Desk\nSerial number:  BW33J1A02345\nHardware board: 53\nFirmware:       1.0.3 (build 218)\nFeatures:       lights";
+        assert_eq!(
+            render(
+                Format::Text,
+                &[report("desk", "192.168.0.25", "BW33J1A02345")]
+            ),
+            desk
+        );
+        let both = render(
+            Format::Text,
+            &[
+                report("desk", "192.168.0.25", "BW33J1A02345"),
+                report("shelf", "192.168.0.26", "BW33J1A06789"),
+            ],
+        );
+        assert!(both.starts_with(&format!("desk:\n{}\n\nshelf:\n", desk)));
+    }
+
+    #[test]
+    fn table_has_one_row_per_device() {
+        assert_eq!(
+            render(
+                Format::Table,
+                &[report("desk", "192.168.0.25", "BW33J1A02345")]
+            ),
+            "NAME                     ADDRESS         PRODUCT                  FIRMWARE   BUILD  SERIAL\n\
+             desk                     192.168.0.25    Elgato Key Light         1.0.3      218    BW33J1A02345"
+        );
+    }
+}
@@ -0,0 +1,521 @@
+use crate::accessory::AccessoryInfo;
+use crate::address::Address;
+use crate::battery::{Battery, BatteryInfo};
+use crate::error::Error;
+use crate::settings::Settings;
+use crate::units::{Brightness, Hue, Kelvin, Saturation};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const PORT: u16 = 9123;
+pub const MIN_MIRED: u16 = 143;
+pub const MAX_MIRED: u16 = 344;
+
+/// How often a fade sends an update to the light.
+const FADE_STEP: Duration = Duration::from_millis(100);
+
+/// How long to wait before the first retry. Each later retry waits twice as long as the last,
+/// up to `MAX_RETRY_BACKOFF`.
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+/// How long to wait for each request, and how many more times to send one that timed out or
+/// could not reach the light.
+#[derive(Debug, Clone, Copy)]
+pub struct Connection {
+    pub timeout: Duration,
+    pub retries: u32,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub number_of_lights: usize,
+    pub lights: Vec<Light>,
+}
+
+/// One light as the device reports it. Color lights such as the Light Strip report a hue and
+/// saturation while showing a color, and may leave out the temperature.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Light {
+    pub on: u8,
+    pub brightness: u8,
+    #[serde(default)]
+    pub temperature: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hue: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f64>,
+}
+
+/// What to change on one light. Fields left as `None` keep the light's current value.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Change {
+    pub on: Option<bool>,
+    pub brightness: Option<Brightness>,
+    pub temperature: Option<Kelvin>,
+    pub hue: Option<Hue>,
+    pub saturation: Option<Saturation>,
+}
+
+/// A change as the device expects it. Fields left as `None` are not sent, so the device
+/// keeps its current value for them.
+#[derive(Default, Debug, Clone, Copy, Serialize)]
+struct LightUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hue: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    saturation: Option<u8>,
+}
+
+impl From<Change> for LightUpdate {
+    fn from(change: Change) -> Self {
+        LightUpdate {
+            on: change.on.map(u8::from),
+            brightness: change.brightness.map(Brightness::get),
+            temperature: change.temperature.map(kelvin_to_mired),
+            hue: change.hue.map(Hue::get),
+            saturation: change.saturation.map(Saturation::get),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusUpdate {
+    number_of_lights: usize,
+    lights: Vec<LightUpdate>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DisplayName<'a> {
+    display_name: &'a str,
+}
+
+/// A client for the HTTP API served by Elgato Key Lights, Ring Lights, and similar devices.
+///
+/// Devices such as the Light Strip report more than one light. Every light is controlled
+/// unless [`KeyLight::select`] narrows it down to one.
+#[derive(Debug)]
+pub struct KeyLight {
+    address: Address,
+    base_url: String,
+    client: reqwest::Client,
+    retries: u32,
+    number_of_lights: usize,
+    index: Option<usize>,
+}
+
+impl KeyLight {
+    /// Connects to the light at `address`, reading its status to confirm it responds.
+    /// `connection` applies to this and every later request.
+    pub async fn new(address: &Address, connection: Connection) -> Result<KeyLight, Error> {
+        let url_host = address.url_host();
+        let socket_addresses = address.resolve(connection.timeout).await?;
+        let client = reqwest::Client::builder()
+            .timeout(connection.timeout)
+            .resolve_to_addrs(&url_host, &socket_addresses)
+            .build()
+            .map_err(|e| Error::request(address, e))?;
+
+        let mut keylight = KeyLight {
+            address: address.clone(),
+            base_url: format!("http://{}:{}/elgato", url_host, address.port()),
+            client,
+            retries: connection.retries,
+            number_of_lights: 0,
+            index: None,
+        };
+
+        keylight.number_of_lights = keylight.get().await?.lights.len();
+        if keylight.number_of_lights == 0 {
+            return Err(Error::MalformedResponse(address.to_string()));
+        }
+        Ok(keylight)
+    }
+
+    /// Limits reads and changes to the light at `index`, or lifts the limit when `None`.
+    pub fn select(&mut self, index: Option<usize>) -> Result<(), Error> {
+        if let Some(index) = index {
+            if index >= self.number_of_lights {
+                return Err(Error::Usage(format!(
+                    "{} has no light at index {}. Its lights are numbered 0 to {}",
+                    self.address,
+                    index,
+                    self.number_of_lights - 1
+                )));
+            }
+        }
+        self.index = index;
+        Ok(())
+    }
+
+    pub async fn get(&self) -> Result<Status, Error> {
+        self.get_json("lights").await
+    }
+
+    pub async fn accessory_info(&self) -> Result<AccessoryInfo, Error> {
+        self.get_json("accessory-info").await
+    }
+
+    pub async fn settings(&self) -> Result<Settings, Error> {
+        self.get_json("lights/settings").await
+    }
+
+    pub async fn set_settings(&self, settings: &Settings) -> Result<(), Error> {
+        self.put_json("lights/settings", settings).await
+    }
+
+    /// Reads the battery and its eco mode settings, or `None` for lights without a battery.
+    pub async fn battery(&self) -> Result<Option<Battery>, Error> {
+        let info: BatteryInfo = match self.get_json("battery-info").await {
+            Ok(info) => info,
+            Err(Error::Http {
+                status: reqwest::StatusCode::NOT_FOUND,
+                ..
+            }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let settings = self
+            .settings()
+            .await?
+            .battery()
+            .transpose()
+            .map_err(|_| Error::MalformedResponse(self.address.to_string()))?;
+        Ok(Some(Battery { info, settings }))
+    }
+
+    /// Makes the light blink a few times.
+    pub async fn identify(&self) -> Result<(), Error> {
+        let url = format!("{}/identify", self.base_url);
+        self.send(self.client.post(url)).await?;
+        Ok(())
+    }
+
+    pub async fn set_display_name(&self, name: &str) -> Result<(), Error> {
+        self.put_json("accessory-info", &DisplayName { display_name: name })
+            .await
+    }
+
+    /// Reads the selected lights, or every light on the device when none is selected.
+    pub async fn lights(&self) -> Result<Vec<Light>, Error> {
+        let lights = self.get().await?.lights;
+        let selected = match self.index {
+            Some(index) => lights.get(index).cloned().into_iter().collect(),
+            None => lights,
+        };
+
+        if selected.is_empty() {
+            return Err(Error::MalformedResponse(self.address.to_string()));
+        }
+        Ok(selected)
+    }
+
+    pub async fn set_power(&mut self, on: bool) -> Result<(), Error> {
+        self.update(|_| Change {
+            on: Some(on),
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn set_brightness(&mut self, brightness: Brightness) -> Result<(), Error> {
+        self.update(|_| Change {
+            brightness: Some(brightness),
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn set_temperature(&mut self, kelvin: Kelvin) -> Result<(), Error> {
+        self.update(|_| Change {
+            temperature: Some(kelvin),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Applies the change `target` picks for each selected light from its current state, in
+    /// the order [`KeyLight::lights`] returns them. With a `transition`, power changes are
+    /// made first and brightness and temperature then fade over it. A zero transition is the
+    /// same as none.
+    pub async fn change(
+        &mut self,
+        target: impl Fn(usize, &Light) -> Change,
+        transition: Option<Duration>,
+    ) -> Result<(), Error> {
+        let lights = self.lights().await?;
+        let changes: Vec<Change> = lights
+            .iter()
+            .enumerate()
+            .map(|(position, light)| target(position, light))
+            .collect();
+
+        match transition.filter(|duration| !duration.is_zero()) {
+            Some(duration) => {
+                if changes.iter().any(|change| change.on.is_some()) {
+                    self.update(|position| Change {
+                        on: changes[position].on,
+                        ..Default::default()
+                    })
+                    .await?;
+                }
+                self.fade(&lights, &changes, duration).await
+            }
+            None => self.update(|position| changes[position]).await,
+        }
+    }
+
+    /// Moves brightness and temperature from their current values to the targets in even
+    /// steps over `duration`, sending only what changed at each step.
+    async fn fade(
+        &mut self,
+        lights: &[Light],
+        changes: &[Change],
+        duration: Duration,
+    ) -> Result<(), Error> {
+        let from: Vec<(f64, f64)> = lights
+            .iter()
+            .map(|light| (f64::from(light.brightness), f64::from(light.temperature)))
+            .collect();
+        let to: Vec<(f64, f64)> = lights
+            .iter()
+            .zip(changes)
+            .map(|(light, change)| {
+                (
+                    f64::from(change.brightness.map_or(light.brightness, Brightness::get)),
+                    f64::from(
+                        change
+                            .temperature
+                            .map_or(light.temperature, kelvin_to_mired),
+                    ),
+                )
+            })
+            .collect();
+
+        let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+        let mut interval = tokio::time::interval(duration / steps);
+        // The first tick completes immediately; skip it so every step waits its share.
+        interval.tick().await;
+        let mut last: Vec<(u8, u16)> = lights
+            .iter()
+            .map(|light| (light.brightness, light.temperature))
+            .collect();
+        for step in 1..=steps {
+            interval.tick().await;
+            let progress = f64::from(step) / f64::from(steps);
+            let next: Vec<(u8, u16)> = from
+                .iter()
+                .zip(&to)
+                .map(|(from, to)| {
+                    (
+                        (from.0 + (to.0 - from.0) * progress).round() as u8,
+                        (from.1 + (to.1 - from.1) * progress).round() as u16,
+                    )
+                })
+                .collect();
+            if next == last {
+                continue;
+            }
+
+            let updates: Vec<LightUpdate> = next
+                .iter()
+                .zip(&last)
+                .map(|(next, last)| LightUpdate {
+                    brightness: (next.0 != last.0).then_some(next.0),
+                    temperature: (next.1 != last.1).then_some(next.1),
+                    ..Default::default()
+                })
+                .collect();
+            self.send_updates(|position| updates[position]).await?;
+            last = next;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the change `change` gives for each selected light, by its position among them.
+    async fn update(&mut self, change: impl Fn(usize) -> Change) -> Result<(), Error> {
+        self.send_updates(|position| change(position).into()).await
+    }
+
+    async fn send_updates(&mut self, update: impl Fn(usize) -> LightUpdate) -> Result<(), Error> {
+        // Lights that are not selected get an empty update, which leaves them as they are.
+        let lights = (0..self.number_of_lights)
+            .map(|index| match self.index {
+                Some(selected) if selected == index => update(0),
+                Some(_) => LightUpdate::default(),
+                None => update(index),
+            })
+            .collect();
+        let update = StatusUpdate {
+            number_of_lights: self.number_of_lights,
+            lights,
+        };
+
+        self.put_json("lights", &update).await
+    }
+
+    /// Reads `path` under the device's `/elgato` API.
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let url = format!("{}/{}", self.base_url, path);
+        self.send(self.client.get(url))
+            .await?
+            .json()
+            .await
+            .map_err(|e| Error::request(&self.address, e))
+    }
+
+    /// Writes `body` to `path` under the device's `/elgato` API.
+    async fn put_json(&self, path: &str, body: &impl Serialize) -> Result<(), Error> {
+        let url = format!("{}/{}", self.base_url, path);
+        self.send(self.client.put(url).json(body)).await?;
+        Ok(())
+    }
+
+    /// Sends a request, retrying with exponential backoff while the light cannot be reached.
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, Error> {
+        let mut attempt = 0;
+        loop {
+            let result = request
+                .try_clone()
+                .expect("light requests have no streaming body")
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+
+            match result.map_err(|e| Error::request(&self.address, e)) {
+                Ok(response) => return Ok(response),
+                Err(Error::Timeout(_) | Error::Unreachable(_)) if attempt < self.retries => {
+                    tokio::time::sleep(retry_backoff(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// How long to wait before retry number `attempt`, counting from zero.
+fn retry_backoff(attempt: u32) -> Duration {
+    RETRY_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_BACKOFF)
+}
+
+/// The light reports and accepts color temperature in mired, one million divided by Kelvin,
+/// limited to 143 (7000 K) through 344 (2900 K).
+pub fn kelvin_to_mired(kelvin: Kelvin) -> u16 {
+    let mired = (1_000_000.0 / f64::from(kelvin.get())).round();
+    mired.clamp(f64::from(MIN_MIRED), f64::from(MAX_MIRED)) as u16
+}
+
+/// Converts back to Kelvin, rounded to the 50 K steps Elgato's own apps use so that a
+/// temperature reads back the way it was typed.
+pub fn mired_to_kelvin(mired: u16) -> u32 {
+    let kelvin = 1_000_000.0 / f64::from(mired.max(1));
+    (kelvin / 50.0).round() as u32 * 50
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A light that closes the first `drops` connections without answering, then serves its
+    /// status.
+    async fn flaky_light(drops: usize) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for _ in 0..drops {
+                drop(listener.accept().await.unwrap());
+            }
+            let body =
+                r#"{"numberOfLights":1,"lights":[{"on":1,"brightness":35,"temperature":222}]}"#;
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await.unwrap() {
+                        0 => break,
+                        read => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        address
+    }
+
+    fn connection(retries: u32) -> Connection {
+        Connection {
+            timeout: Duration::from_secs(5),
+            retries,
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_connections_are_retried() {
+        let address = Address::from(flaky_light(2).await);
+        let keylight = KeyLight::new(&address, connection(2)).await.unwrap();
+        assert_eq!(keylight.lights().await.unwrap()[0].brightness, 35);
+    }
+
+    #[tokio::test]
+    async fn gives_up_once_the_retries_run_out() {
+        let address = Address::from(flaky_light(2).await);
+        let result = KeyLight::new(&address, connection(1)).await;
+        assert!(matches!(result, Err(Error::Unreachable(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn retry_backoff_doubles_up_to_the_cap() {
+        let backoff: Vec<_> = (0..7)
+            .map(|attempt| retry_backoff(attempt).as_millis())
+            .collect();
+        assert_eq!(backoff, [200, 400, 800, 1600, 2000, 2000, 2000]);
+        assert_eq!(retry_backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+
+    #[test]
+    fn kelvin_round_trips_through_mired() {
+        for kelvin in (Kelvin::MIN.get()..=Kelvin::MAX.get()).step_by(50) {
+            let mired = kelvin_to_mired(Kelvin::clamped(kelvin));
+            assert_eq!(mired_to_kelvin(mired), kelvin, "{} K", kelvin);
+        }
+    }
+
+    #[test]
+    fn temperature_shown_for_any_mired_sets_the_same_temperature() {
+        // Several mired values share a 50 K step, so only the Kelvin reading is stable.
+        for mired in MIN_MIRED..=MAX_MIRED {
+            let shown = mired_to_kelvin(mired);
+            let set = kelvin_to_mired(Kelvin::clamped(shown));
+            assert_eq!(mired_to_kelvin(set), shown, "{} mired", mired);
+        }
+    }
+
+    #[test]
+    fn kelvin_range_matches_mired_range() {
+        assert_eq!(kelvin_to_mired(Kelvin::MIN), MAX_MIRED);
+        assert_eq!(kelvin_to_mired(Kelvin::MAX), MIN_MIRED);
+    }
+}
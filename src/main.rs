@@ -1,10 +1,280 @@
-use elgato_keylight::KeyLight;
-use std::error::Error;
-use std::net::Ipv4Addr;
+mod accessory;
+mod address;
+mod battery;
+mod bridge;
+mod color;
+mod config;
+mod discovery;
+mod error;
+mod info;
+mod keylight;
+mod mqtt;
+mod report;
+mod scene;
+mod serve;
+mod settings;
+mod status;
+#[cfg(test)]
+mod testing;
+mod units;
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::process;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
 
-const DEFAULT_IP_ADDRESS: &str = "192.168.0.25";
+use accessory::AccessoryInfo;
+use address::Address;
+use battery::Battery;
+use color::Color;
+use config::Config;
+use error::Error;
+use keylight::{mired_to_kelvin, Change, Connection, KeyLight, Light, Status};
+use report::{Format, Report};
+use scene::{LightState, Scene, Scenes};
+use settings::{PowerOnBehavior, SettingsChange};
+use units::{Brightness, Hue, Kelvin, Saturation};
+
+/// How far `temperature --warmer` and `--cooler` move the light, in Kelvin.
+const TEMPERATURE_STEP: i32 = 500;
+
+/// How often `status --watch` reads the lights unless `--interval` says otherwise.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The outcome of running something against one light: its label, address, and result.
+type Outcome<T> = (String, Address, Result<T, Error>);
+
+/// What a command read from a light, printed once every light has answered.
+enum Output {
+    Status(status::Snapshot),
+    Info(AccessoryInfo),
+    Battery(Battery),
+}
+
+/// Outcomes split into the lights that succeeded and the errors of those that failed.
+struct Settled<T> {
+    successes: Vec<(String, Address, T)>,
+    failures: Vec<Error>,
+    total: usize,
+}
+
+/// A light followed by `status --watch`, with what it looked like when last read.
+struct Watched {
+    name: String,
+    address: Address,
+    keylight: Arc<KeyLight>,
+    last: Option<Status>,
+    failing: bool,
+}
+
+impl<T> Settled<T> {
+    fn check(&self) -> Result<(), Error> {
+        if !self.failures.is_empty() {
+            return Err(Error::lights_failed(&self.failures, self.total));
+        }
+        Ok(())
+    }
+
+    /// Confirms each light that succeeded by name, when there was more than one to tell apart.
+    fn print_ok(&self) {
+        if self.total > 1 {
+            for (label, _, _) in &self.successes {
+                println!("{}: ok", label);
+            }
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Default)]
+struct Target {
+    #[structopt(
+        short = "i",
+        long = "ip-address",
+        number_of_values = 1,
+        help = "Specify the IP address or hostname of the Elgato Light, optionally followed by :port. Repeat to control several lights. Defaults to ELGATO_LIGHT_IP_ADDRESS, then ip_address in the config file"
+    )]
+    ip_address: Vec<String>,
+
+    #[structopt(
+        short = "l",
+        long = "light",
+        number_of_values = 1,
+        help = "Use a light by the name given to it in the config file. Repeat to control several lights"
+    )]
+    light: Vec<String>,
+
+    #[structopt(
+        short = "g",
+        long = "group",
+        help = "Use every light in a group defined in the config file"
+    )]
+    group: Option<String>,
+
+    #[structopt(flatten)]
+    connection: ConnectionOptions,
+}
+
+#[derive(StructOpt, Debug, Default)]
+struct ConnectionOptions {
+    #[structopt(
+        long = "timeout",
+        env = "ELGATO_LIGHT_TIMEOUT",
+        parse(try_from_str = humantime::parse_duration),
+        help = "How long to wait for each response from the light [default: 5s]"
+    )]
+    timeout: Option<Duration>,
+
+    #[structopt(
+        long = "retries",
+        env = "ELGATO_LIGHT_RETRIES",
+        help = "How many times to retry a request that times out or cannot reach the light [default: 2]"
+    )]
+    retries: Option<u32>,
+}
+
+#[derive(StructOpt, Debug, Default)]
+struct Channels {
+    #[structopt(
+        long = "index",
+        conflicts_with = "all-channels",
+        help = "Only change the light at this index on devices that have several, starting from 0"
+    )]
+    index: Option<usize>,
+
+    #[structopt(
+        long = "all-channels",
+        help = "Change every light on devices that have several. This is the default"
+    )]
+    all_channels: bool,
+}
+
+impl Channels {
+    fn index(&self) -> Option<usize> {
+        if self.all_channels {
+            None
+        } else {
+            self.index
+        }
+    }
+}
+
+impl ConnectionOptions {
+    fn resolve(&self, config: &Config) -> Connection {
+        Connection {
+            timeout: self
+                .timeout
+                .or(config.timeout)
+                .unwrap_or(config::DEFAULT_TIMEOUT),
+            retries: self
+                .retries
+                .or(config.retries)
+                .unwrap_or(config::DEFAULT_RETRIES),
+        }
+    }
+}
+
+impl Target {
+    /// Resolves the requested lights to `(label, address)` pairs, where the label is the
+    /// configured name when there is one and the address otherwise.
+    fn lights(&self, config: &Config) -> Result<Vec<(String, Address)>, Error> {
+        let mut lights: Vec<(String, &str)> = Vec::new();
+
+        for ip_address in &self.ip_address {
+            lights.push((ip_address.clone(), ip_address));
+        }
+        for name in &self.light {
+            lights.push((name.clone(), config.light(name)?));
+        }
+        if let Some(group) = &self.group {
+            for member in config.group(group)? {
+                lights.push((member.clone(), config.address(member)));
+            }
+        }
+
+        if lights.is_empty() {
+            let ip_address = config.ip_address.as_deref().ok_or_else(|| {
+                    Error::Usage("No IP address given. Use --ip-address, --light, --group, ELGATO_LIGHT_IP_ADDRESS, or set ip_address in the config file".to_string())
+                })?;
+            lights.push((ip_address.to_string(), ip_address));
+        }
+
+        parse_addresses(lights)
+    }
+}
+
+/// A brightness to set outright, or a signed change in percentage points from the current one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BrightnessChange {
+    Absolute(Brightness),
+    Relative(i8),
+}
+
+impl FromStr for BrightnessChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('=') {
+            Some(value) => value.parse().map(BrightnessChange::Absolute),
+            None => match s.parse::<i64>() {
+                Ok(delta @ -100..=100) => Ok(BrightnessChange::Relative(delta as i8)),
+                Ok(_) => Err(format!(
+                    "Brightness changes must be between -100 and 100, got {}. Use =N to set an exact level",
+                    s
+                )),
+                Err(_) => Err(format!("'{}' is not a brightness change", s)),
+            },
+        }
+    }
+}
+
+/// A temperature to set outright, or a signed change in Kelvin from the current one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TemperatureChange {
+    Absolute(Kelvin),
+    Relative(i32),
+}
+
+impl FromStr for TemperatureChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('+') || s.starts_with('-') {
+            s.parse()
+                .map(TemperatureChange::Relative)
+                .map_err(|_| format!("'{}' is not a change in Kelvin", s))
+        } else {
+            s.parse().map(TemperatureChange::Absolute)
+        }
+    }
+}
+
+/// Parses `(label, address)` pairs, dropping any address that appears more than once.
+fn parse_addresses<'a>(
+    lights: impl IntoIterator<Item = (String, &'a str)>,
+) -> Result<Vec<(String, Address)>, Error> {
+    let mut parsed: Vec<(String, Address)> = Vec::new();
+    for (label, address) in lights {
+        let address = Address::from_str(address)
+            .map_err(|e| Error::InvalidAddress(format!("Invalid address for {}: {}", label, e)))?;
+        if !parsed.iter().any(|(_, existing)| *existing == address) {
+            parsed.push((label, address));
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Parses how often to repeat something, which has to be longer than zero.
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    let interval = humantime::parse_duration(s).map_err(|e| e.to_string())?;
+    if interval.is_zero() {
+        return Err("The interval must be longer than 0s".to_string());
+    }
+    Ok(interval)
+}
 
 #[derive(StructOpt, Debug)]
 #[structopt(
@@ -17,119 +287,1389 @@ enum ElgatoLight {
         #[structopt(
             short = "b",
             long = "brightness",
-            default_value = "10",
-            help = "Set the brightness level (0-100)"
+            env = "ELGATO_LIGHT_BRIGHTNESS",
+            help = "Set the brightness level (0-100) [default: 10]"
         )]
-        brightness: u8,
+        brightness: Option<Brightness>,
 
         #[structopt(
             short = "t",
             long = "temperature",
-            default_value = "3000",
-            help = "Set the color temperature (2900-7000)"
+            env = "ELGATO_LIGHT_TEMPERATURE",
+            help = "Set the color temperature (2900-7000) [default: 3000]"
+        )]
+        temperature: Option<Kelvin>,
+
+        #[structopt(
+            long = "transition",
+            parse(try_from_str = humantime::parse_duration),
+            help = "Fade to the new setting over this long, such as 2s or 500ms"
         )]
-        temperature: u32,
+        transition: Option<Duration>,
+
+        #[structopt(flatten)]
+        channels: Channels,
 
-        #[structopt(short = "i", long = "ip-address", default_value = DEFAULT_IP_ADDRESS, help = "Specify the IP address of the Elgato Light")]
-        ip_address: String,
+        #[structopt(flatten)]
+        target: Target,
     },
     #[structopt(about = "Turns the light off")]
     Off {
-        #[structopt(short = "i", long = "ip-address", default_value = DEFAULT_IP_ADDRESS, help = "Specify the IP address of the Elgato Light")]
-        ip_address: String,
+        #[structopt(
+            long = "transition",
+            parse(try_from_str = humantime::parse_duration),
+            help = "Fade out over this long, such as 2s or 500ms"
+        )]
+        transition: Option<Duration>,
+
+        #[structopt(flatten)]
+        channels: Channels,
+
+        #[structopt(flatten)]
+        target: Target,
     },
     #[structopt(
-        about = "Changes the brightness of the light. Use -100 to 100. Use -- to pass negative arguments."
+        about = "Turns the light off if it is on, or on if it is off. Brightness and temperature are only changed when given"
+    )]
+    Toggle {
+        #[structopt(
+            short = "b",
+            long = "brightness",
+            help = "Set the brightness level (0-100) when turning on"
+        )]
+        brightness: Option<Brightness>,
+
+        #[structopt(
+            short = "t",
+            long = "temperature",
+            help = "Set the color temperature (2900-7000) when turning on"
+        )]
+        temperature: Option<Kelvin>,
+
+        #[structopt(
+            long = "transition",
+            parse(try_from_str = humantime::parse_duration),
+            help = "Fade in or out over this long, such as 2s or 500ms"
+        )]
+        transition: Option<Duration>,
+
+        #[structopt(flatten)]
+        channels: Channels,
+
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(
+        about = "Changes the brightness of the light. Use -100 to 100, or =N to set it to exactly N. Use -- to pass negative arguments."
     )]
     Brightness {
-        #[structopt(help = "Change the brightness level (-100 to 100)")]
-        brightness: i8,
+        #[structopt(
+            help = "Change the brightness level (-100 to 100), or set it with =N (0 to 100)"
+        )]
+        brightness: BrightnessChange,
 
-        #[structopt(short = "i", long = "ip-address", default_value = DEFAULT_IP_ADDRESS, help = "Specify the IP address of the Elgato Light")]
-        ip_address: String,
+        #[structopt(
+            long = "transition",
+            parse(try_from_str = humantime::parse_duration),
+            help = "Fade to the new setting over this long, such as 2s or 500ms"
+        )]
+        transition: Option<Duration>,
+
+        #[structopt(flatten)]
+        channels: Channels,
+
+        #[structopt(flatten)]
+        target: Target,
     },
-    #[structopt(about = "Sets the temperature of the light")]
+    #[structopt(
+        about = "Sets the temperature of the light, or changes it with +N or -N Kelvin. Use -- to pass negative arguments."
+    )]
     Temperature {
-        #[structopt(help = "Set the color temperature (2900-7000)")]
-        temperature: u32,
+        #[structopt(
+            required_unless_one = &["warmer", "cooler"],
+            conflicts_with_all = &["warmer", "cooler"],
+            help = "Set the color temperature (2900-7000), or change it by +N or -N Kelvin"
+        )]
+        temperature: Option<TemperatureChange>,
+
+        #[structopt(
+            long = "warmer",
+            conflicts_with = "cooler",
+            help = "Make the light 500 K warmer"
+        )]
+        warmer: bool,
+
+        #[structopt(long = "cooler", help = "Make the light 500 K cooler")]
+        cooler: bool,
+
+        #[structopt(
+            long = "transition",
+            parse(try_from_str = humantime::parse_duration),
+            help = "Fade to the new setting over this long, such as 2s or 500ms"
+        )]
+        transition: Option<Duration>,
+
+        #[structopt(flatten)]
+        channels: Channels,
 
-        #[structopt(short = "i", long = "ip-address", default_value = DEFAULT_IP_ADDRESS, help = "Specify the IP address of the Elgato Light")]
-        ip_address: String,
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(
+        about = "Sets the color of lights that support it, such as the Light Strip, by name, #RRGGBB, or hue and saturation"
+    )]
+    Color {
+        #[structopt(
+            required_unless_one = &["hue", "saturation"],
+            conflicts_with_all = &["hue", "saturation"],
+            help = "A CSS color name such as orange, or #RRGGBB"
+        )]
+        color: Option<Color>,
+
+        #[structopt(long = "hue", help = "Set the hue in degrees (0-360)")]
+        hue: Option<Hue>,
+
+        #[structopt(long = "saturation", help = "Set the saturation (0-100)")]
+        saturation: Option<Saturation>,
+
+        #[structopt(
+            short = "b",
+            long = "brightness",
+            help = "Set the brightness level (0-100). A named or #RRGGBB color sets its own unless this is given"
+        )]
+        brightness: Option<Brightness>,
+
+        #[structopt(flatten)]
+        channels: Channels,
+
+        #[structopt(flatten)]
+        target: Target,
     },
     #[structopt(about = "Gets the status of the light")]
     Status {
-        #[structopt(short = "i", long = "ip-address", default_value = DEFAULT_IP_ADDRESS, help = "Specify the IP address of the Elgato Light")]
-        ip_address: String,
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "text",
+            possible_values = &["text", "json", "table"],
+            help = "Output format"
+        )]
+        format: Format,
+
+        #[structopt(
+            short = "w",
+            long = "watch",
+            help = "Keep reading the lights and print their status again whenever power, brightness, or temperature changes"
+        )]
+        watch: bool,
+
+        #[structopt(
+            long = "interval",
+            requires = "watch",
+            parse(try_from_str = parse_interval),
+            help = "How often to read the lights with --watch [default: 2s]"
+        )]
+        interval: Option<Duration>,
+
+        #[structopt(
+            long = "exit-on-change",
+            requires = "watch",
+            help = "Stop watching after the first change"
+        )]
+        exit_on_change: bool,
+
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(
+        about = "Shows the charge, charging state, power source, and eco mode of battery-powered lights"
+    )]
+    Battery {
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "text",
+            possible_values = &["text", "json", "table"],
+            help = "Output format"
+        )]
+        format: Format,
+
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(
+        about = "Shows the product, firmware, serial number, and features the light reports"
+    )]
+    Info {
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "text",
+            possible_values = &["text", "json", "table"],
+            help = "Output format"
+        )]
+        format: Format,
+
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(
+        about = "Sets the name the light shows in discovery and in Elgato's apps. Without a name, each light takes the one it has in the config file"
+    )]
+    Rename {
+        #[structopt(help = "New display name for the light")]
+        name: Option<String>,
+
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(about = "Makes the light blink so you can tell which one it is")]
+    Identify {
+        #[structopt(
+            long = "all",
+            conflicts_with_all = &["ip-address", "light", "group"],
+            help = "Find every light on the network and make each one blink in turn, printing its IP address first"
+        )]
+        all: bool,
+
+        #[structopt(
+            long = "pause",
+            default_value = "3s",
+            parse(try_from_str = humantime::parse_duration),
+            help = "How long to wait before moving on to the next light with --all"
+        )]
+        pause: Duration,
+
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(about = "Finds Elgato lights on the local network")]
+    Discover {
+        #[structopt(
+            long = "timeout",
+            env = "ELGATO_LIGHT_DISCOVER_TIMEOUT",
+            parse(try_from_str = humantime::parse_duration),
+            help = "How long to wait for lights to respond [default: 3s]"
+        )]
+        timeout: Option<Duration>,
+    },
+    #[structopt(
+        about = "Runs an HTTP server that controls the lights, addressed by their name in the config file"
+    )]
+    Serve {
+        #[structopt(
+            long = "listen",
+            env = "ELGATO_LIGHT_LISTEN",
+            default_value = "127.0.0.1:9124",
+            help = "Address and port to listen on. Use 0.0.0.0:9124 to accept requests from other machines"
+        )]
+        listen: SocketAddr,
+
+        #[structopt(flatten)]
+        connection: ConnectionOptions,
+    },
+    #[structopt(
+        about = "Bridges the lights in the config file to an MQTT broker, announcing them to Home Assistant"
+    )]
+    Mqtt {
+        #[structopt(
+            long = "broker",
+            env = "ELGATO_LIGHT_MQTT_BROKER",
+            default_value = "localhost:1883",
+            help = "Host of the MQTT broker, optionally followed by :port"
+        )]
+        broker: String,
+
+        #[structopt(
+            long = "username",
+            env = "ELGATO_LIGHT_MQTT_USERNAME",
+            help = "Username to log in to the broker with"
+        )]
+        username: Option<String>,
+
+        #[structopt(
+            long = "password",
+            env = "ELGATO_LIGHT_MQTT_PASSWORD",
+            hide_env_values = true,
+            help = "Password to log in to the broker with"
+        )]
+        password: Option<String>,
+
+        #[structopt(
+            long = "topic-prefix",
+            default_value = "elgato",
+            help = "Topics are <prefix>/<light name>/set, state, and availability"
+        )]
+        topic_prefix: String,
+
+        #[structopt(
+            long = "discovery-prefix",
+            default_value = "homeassistant",
+            help = "Home Assistant's MQTT discovery prefix"
+        )]
+        discovery_prefix: String,
+
+        #[structopt(
+            long = "poll-interval",
+            default_value = "5s",
+            parse(try_from_str = parse_interval),
+            help = "How often to read the lights to publish changes made elsewhere"
+        )]
+        poll_interval: Duration,
+
+        #[structopt(flatten)]
+        connection: ConnectionOptions,
+    },
+    #[structopt(about = "Saves and applies named scenes")]
+    Scene(SceneCommand),
+    #[structopt(
+        about = "Shows and changes the settings the light keeps when it loses power, such as how it comes back on"
+    )]
+    Settings(SettingsCommand),
+}
+
+#[derive(StructOpt, Debug)]
+enum SceneCommand {
+    #[structopt(about = "Saves the current power, brightness, and temperature of the lights")]
+    Save {
+        #[structopt(help = "Name of the scene")]
+        name: String,
+
+        #[structopt(flatten)]
+        target: Target,
     },
+    #[structopt(about = "Restores every light saved in a scene")]
+    Apply {
+        #[structopt(help = "Name of the scene")]
+        name: String,
+
+        #[structopt(flatten)]
+        connection: ConnectionOptions,
+    },
+    #[structopt(about = "Lists the saved scenes")]
+    List,
+}
+
+#[derive(StructOpt, Debug)]
+enum SettingsCommand {
+    #[structopt(about = "Shows the power-on behavior and switch durations of the lights")]
+    Show {
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "text",
+            possible_values = &["text", "json", "table"],
+            help = "Output format"
+        )]
+        format: Format,
+
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(about = "Changes the given settings and keeps the rest")]
+    Set {
+        #[structopt(
+            long = "power-on",
+            possible_values = &["restore", "fixed"],
+            help = "Come back on the way the light was when it lost power, or at the power-on brightness and temperature"
+        )]
+        power_on: Option<PowerOnBehavior>,
+
+        #[structopt(
+            long = "power-on-brightness",
+            help = "Brightness level (0-100) to come back on at with --power-on fixed"
+        )]
+        power_on_brightness: Option<Brightness>,
+
+        #[structopt(
+            long = "power-on-temperature",
+            help = "Color temperature (2900-7000) to come back on at with --power-on fixed"
+        )]
+        power_on_temperature: Option<Kelvin>,
+
+        #[structopt(
+            long = "switch-on-duration",
+            parse(try_from_str = humantime::parse_duration),
+            help = "How long the light takes to fade in when switched on, such as 100ms"
+        )]
+        switch_on_duration: Option<Duration>,
+
+        #[structopt(
+            long = "switch-off-duration",
+            parse(try_from_str = humantime::parse_duration),
+            help = "How long the light takes to fade out when switched off, such as 300ms"
+        )]
+        switch_off_duration: Option<Duration>,
+
+        #[structopt(flatten)]
+        target: Target,
+    },
+}
+
+impl SettingsCommand {
+    async fn run(self, config: Config) -> Result<(), Error> {
+        match self {
+            SettingsCommand::Show { format, target } => {
+                let lights = target.lights(&config)?;
+                let connection = target.connection.resolve(&config);
+                let outcomes = ElgatoLight::fan_out(lights, connection, |_, keylight| async move {
+                    keylight.settings().await
+                })
+                .await?;
+
+                let settled = ElgatoLight::settle(outcomes)?;
+                let checked = settled.check();
+                let reports: Vec<Report<_>> =
+                    settled.successes.into_iter().map(Report::from).collect();
+                if format.prints(reports.len()) {
+                    println!("{}", report::render(format, &reports));
+                }
+                checked?;
+            }
+            SettingsCommand::Set {
+                power_on,
+                power_on_brightness,
+                power_on_temperature,
+                switch_on_duration,
+                switch_off_duration,
+                target,
+            } => {
+                let change = SettingsChange {
+                    power_on_behavior: power_on,
+                    power_on_brightness,
+                    power_on_temperature,
+                    switch_on_duration,
+                    switch_off_duration,
+                };
+                if change.is_empty() {
+                    return Err(Error::Usage(
+                        "Give at least one setting to change. See elgato-light settings set --help"
+                            .to_string(),
+                    ));
+                }
+
+                let lights = target.lights(&config)?;
+                let connection = target.connection.resolve(&config);
+                let outcomes =
+                    ElgatoLight::fan_out(lights, connection, move |_, keylight| async move {
+                        let mut settings = keylight.settings().await?;
+                        change.apply(&mut settings);
+                        keylight.set_settings(&settings).await
+                    })
+                    .await?;
+
+                let settled = ElgatoLight::settle(outcomes)?;
+                settled.print_ok();
+                settled.check()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SceneCommand {
+    async fn run(self, config: Config) -> Result<(), Error> {
+        let mut scenes = Scenes::load()?;
+
+        match self {
+            SceneCommand::Save { name, target } => {
+                let lights = target.lights(&config)?;
+                let connection = target.connection.resolve(&config);
+                let outcomes =
+                    ElgatoLight::fan_out(lights, connection, |label, keylight| async move {
+                        let states = LightState::from_status(&keylight.get().await?);
+                        if states.is_empty() {
+                            return Err(Error::MalformedResponse(label));
+                        }
+                        Ok(states)
+                    })
+                    .await?;
+
+                let settled = ElgatoLight::settle(outcomes)?;
+                if let Err(e) = settled.check() {
+                    eprintln!("Scene '{}' was not saved", name);
+                    return Err(e);
+                }
+
+                let scene: Scene = settled
+                    .successes
+                    .into_iter()
+                    .map(|(label, _, state)| (label, state))
+                    .collect();
+                scenes.save(&name, scene)?;
+            }
+            SceneCommand::Apply { name, connection } => {
+                let connection = connection.resolve(&config);
+                let scene = Arc::new(scenes.get(&name)?.clone());
+                let lights = parse_addresses(
+                    scene
+                        .keys()
+                        .map(|label| (label.clone(), config.address(label))),
+                )?;
+
+                let outcomes =
+                    ElgatoLight::fan_out(lights, connection, move |label, mut keylight| {
+                        let states = scene[&label].clone();
+                        async move {
+                            // Each light takes its own first step before any takes its second,
+                            // and lights the scene has no state for are left as they are.
+                            for step in 0..2 {
+                                let change = |position: usize, _: &Light| {
+                                    states
+                                        .get(position)
+                                        .map_or_else(Change::default, |state| state.changes()[step])
+                                };
+                                keylight.change(change, None).await?;
+                            }
+                            Ok(())
+                        }
+                    })
+                    .await?;
+
+                let settled = ElgatoLight::settle(outcomes)?;
+                settled.print_ok();
+                settled.check()?;
+            }
+            SceneCommand::List => {
+                for name in scenes.names() {
+                    println!("{}", name);
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl ElgatoLight {
-    fn ip_address(&self) -> Result<Ipv4Addr, Box<dyn Error>> {
-        let ip_str = match self {
-            ElgatoLight::On { ip_address, .. }
-            | ElgatoLight::Off { ip_address }
-            | ElgatoLight::Brightness { ip_address, .. }
-            | ElgatoLight::Temperature { ip_address, .. }
-            | ElgatoLight::Status { ip_address } => ip_address,
-        };
+    fn target(&self) -> &Target {
+        match self {
+            ElgatoLight::On { target, .. }
+            | ElgatoLight::Off { target, .. }
+            | ElgatoLight::Toggle { target, .. }
+            | ElgatoLight::Brightness { target, .. }
+            | ElgatoLight::Temperature { target, .. }
+            | ElgatoLight::Color { target, .. }
+            | ElgatoLight::Status { target, .. }
+            | ElgatoLight::Info { target, .. }
+            | ElgatoLight::Battery { target, .. }
+            | ElgatoLight::Rename { target, .. }
+            | ElgatoLight::Identify { target, .. } => target,
+            ElgatoLight::Discover { .. }
+            | ElgatoLight::Serve { .. }
+            | ElgatoLight::Mqtt { .. }
+            | ElgatoLight::Scene(_)
+            | ElgatoLight::Settings(_) => unreachable!("handled before targeting lights"),
+        }
+    }
 
-        Ipv4Addr::from_str(ip_str).map_err(|_| "Invalid IP address format".into())
+    fn channels(&self) -> Option<&Channels> {
+        match self {
+            ElgatoLight::On { channels, .. }
+            | ElgatoLight::Off { channels, .. }
+            | ElgatoLight::Toggle { channels, .. }
+            | ElgatoLight::Brightness { channels, .. }
+            | ElgatoLight::Temperature { channels, .. }
+            | ElgatoLight::Color { channels, .. } => Some(channels),
+            _ => None,
+        }
     }
 
-    async fn get_keylight(ip_address: Ipv4Addr) -> Result<KeyLight, Box<dyn Error>> {
-        let keylight = KeyLight::new_from_ip("Elgato Light", ip_address, None).await?;
+    async fn get_keylight(address: &Address, connection: Connection) -> Result<KeyLight, Error> {
+        let keylight = KeyLight::new(address, connection).await?;
         Ok(keylight)
     }
 
-    async fn ensure_light_on(keylight: &mut KeyLight) -> Result<(), Box<dyn Error>> {
-        let status = keylight.get().await?;
-        if status.lights[0].on == 0 {
+    async fn discover(timeout: Duration, connection: Connection) -> Result<(), Error> {
+        let lights = discovery::discover(timeout, connection).await?;
+        if lights.is_empty() {
+            eprintln!("No Elgato lights found");
+            return Ok(());
+        }
+
+        println!("{:<32} {:<15} {:<5} SERIAL", "NAME", "IP ADDRESS", "PORT");
+        for light in lights {
+            println!(
+                "{:<32} {:<15} {:<5} {}",
+                light.name,
+                light.ip_address.to_string(),
+                light.port,
+                light.serial_number.as_deref().unwrap_or("-")
+            );
+        }
+        Ok(())
+    }
+
+    /// Finds every light on the network and makes each one blink in turn, so that addresses
+    /// can be matched to physical lights.
+    async fn identify_all(
+        discover_timeout: Duration,
+        pause: Duration,
+        connection: Connection,
+    ) -> Result<(), Error> {
+        let lights = discovery::discover(discover_timeout, connection).await?;
+        if lights.is_empty() {
+            eprintln!("No Elgato lights found");
+            return Ok(());
+        }
+
+        let total = lights.len();
+        let mut failures = Vec::new();
+        for (position, light) in lights.into_iter().enumerate() {
+            let address = Address::from(SocketAddr::new(light.ip_address.into(), light.port));
+            println!("{} {}", address, light.name);
+
+            let identified = async { KeyLight::new(&address, connection).await?.identify().await };
+            if let Err(e) = identified.await {
+                eprintln!("{}: {}", address, e);
+                failures.push(e);
+                continue;
+            }
+
+            if position + 1 < total {
+                tokio::time::sleep(pause).await;
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(Error::lights_failed(&failures, total));
+        }
+        Ok(())
+    }
+
+    /// Reads the lights every `interval` and prints the ones whose status changed, starting
+    /// with how they all look now. A light that stops answering is reported once and watched
+    /// until it comes back.
+    async fn watch(
+        format: Format,
+        interval: Duration,
+        exit_on_change: bool,
+        target: &Target,
+        config: &Config,
+    ) -> Result<(), Error> {
+        let lights = target.lights(config)?;
+        let connection = target.connection.resolve(config);
+        let labelled = lights.len() > 1;
+        let outcomes = ElgatoLight::fan_out(lights, connection, |_, keylight| async move {
+            Ok(Arc::new(keylight))
+        })
+        .await?;
+        let settled = ElgatoLight::settle(outcomes)?;
+        settled.check()?;
+
+        let mut watched: Vec<Watched> = settled
+            .successes
+            .into_iter()
+            .map(|(name, address, keylight)| Watched {
+                name,
+                address,
+                keylight,
+                last: None,
+                failing: false,
+            })
+            .collect();
+
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut first = true;
+        loop {
+            ticker.tick().await;
+            let reads: Vec<_> = watched
+                .iter()
+                .map(|light| {
+                    let keylight = Arc::clone(&light.keylight);
+                    tokio::spawn(async move { keylight.get().await })
+                })
+                .collect();
+
+            let mut changed = Vec::new();
+            for (light, read) in watched.iter_mut().zip(reads) {
+                match read.await.expect("light task panicked") {
+                    Ok(status) => {
+                        light.failing = false;
+                        if light.last.as_ref() != Some(&status) {
+                            light.last = Some(status.clone());
+                            changed.push(status::Report {
+                                name: light.name.clone(),
+                                address: light.address.clone(),
+                                reading: status::Snapshot {
+                                    status,
+                                    battery: None,
+                                },
+                            });
+                        }
+                    }
+                    Err(e) if !light.failing => {
+                        eprintln!("{}: {}", light.name, e);
+                        light.failing = true;
+                    }
+                    Err(_) => {}
+                }
+            }
+
+            if !changed.is_empty() {
+                println!(
+                    "{}",
+                    status::render_update(format, &changed, labelled, first)
+                );
+                if exit_on_change && !first {
+                    return Ok(());
+                }
+            }
+            first = false;
+        }
+    }
+
+    async fn ensure_light_on(keylight: &mut KeyLight) -> Result<(), Error> {
+        let lights = keylight.lights().await?;
+        if lights.iter().any(|light| light.on == 0) {
             keylight.set_power(true).await?;
         }
         Ok(())
     }
 
-    async fn run(&self, mut keylight: KeyLight) -> Result<(), Box<dyn Error>> {
+    /// Turns the light on, applying the brightness and temperature that are given and keeping
+    /// the light's previous values for the rest.
+    async fn turn_on(
+        keylight: &mut KeyLight,
+        brightness: Option<Brightness>,
+        temperature: Option<Kelvin>,
+        transition: Option<Duration>,
+    ) -> Result<(), Error> {
+        match transition.filter(|duration| !duration.is_zero()) {
+            Some(duration) => {
+                let lights = keylight.lights().await?;
+                if lights.iter().any(|light| light.on == 0) {
+                    // Lights that are off start from darkness so they fade in.
+                    keylight
+                        .change(
+                            |_, light| match light.on {
+                                0 => Change {
+                                    on: Some(true),
+                                    brightness: Some(Brightness::MIN),
+                                    ..Default::default()
+                                },
+                                _ => Change::default(),
+                            },
+                            None,
+                        )
+                        .await?;
+                }
+                keylight
+                    .change(
+                        |position, _| Change {
+                            brightness: Some(
+                                brightness
+                                    .unwrap_or(Brightness::clamped(lights[position].brightness)),
+                            ),
+                            temperature,
+                            ..Default::default()
+                        },
+                        Some(duration),
+                    )
+                    .await?;
+            }
+            None => {
+                keylight.set_power(true).await?;
+                if let Some(brightness) = brightness {
+                    keylight.set_brightness(brightness).await?;
+                }
+                if let Some(temperature) = temperature {
+                    keylight.set_temperature(temperature).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn turn_off(keylight: &mut KeyLight, transition: Option<Duration>) -> Result<(), Error> {
+        let lights = keylight.lights().await?;
+        match transition.filter(|duration| !duration.is_zero()) {
+            Some(duration) if lights.iter().any(|light| light.on != 0) => {
+                keylight
+                    .change(
+                        |_, _| Change {
+                            brightness: Some(Brightness::MIN),
+                            ..Default::default()
+                        },
+                        Some(duration),
+                    )
+                    .await?;
+                // Put the brightness back so the lights come on the way they were.
+                keylight
+                    .change(
+                        |position, _| Change {
+                            on: Some(false),
+                            brightness: Some(Brightness::clamped(lights[position].brightness)),
+                            ..Default::default()
+                        },
+                        None,
+                    )
+                    .await?;
+            }
+            _ => keylight.set_power(false).await?,
+        }
+        Ok(())
+    }
+
+    /// Connects to every light and runs `action` against each one in its own task, so a slow
+    /// or unreachable light does not hold up the others.
+    async fn fan_out<T, F, Fut>(
+        lights: Vec<(String, Address)>,
+        connection: Connection,
+        action: F,
+    ) -> Result<Vec<Outcome<T>>, Error>
+    where
+        F: Fn(String, KeyLight) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<T, Error>> + Send,
+        T: Send + 'static,
+    {
+        let tasks: Vec<_> = lights
+            .into_iter()
+            .map(|(label, address)| {
+                let action = action.clone();
+                let name = label.clone();
+                let light_address = address.clone();
+                tokio::spawn(async move {
+                    let result = async move {
+                        let keylight =
+                            ElgatoLight::get_keylight(&light_address, connection).await?;
+                        action(name, keylight).await
+                    }
+                    .await;
+                    (label, address, result)
+                })
+            })
+            .collect();
+
+        let mut outcomes = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            outcomes.push(task.await.expect("light task panicked"));
+        }
+        Ok(outcomes)
+    }
+
+    /// Separates successful outcomes from failures, printing each failure next to its light.
+    /// When only one light was targeted its error is returned as-is instead.
+    fn settle<T>(outcomes: Vec<Outcome<T>>) -> Result<Settled<T>, Error> {
+        let total = outcomes.len();
+        let mut successes = Vec::new();
+        let mut failures = Vec::new();
+        for (label, address, result) in outcomes {
+            match result {
+                Ok(value) => successes.push((label, address, value)),
+                Err(e) if total == 1 => return Err(e),
+                Err(e) => {
+                    eprintln!("{}: {}", label, e);
+                    failures.push(e);
+                }
+            }
+        }
+        Ok(Settled {
+            successes,
+            failures,
+            total,
+        })
+    }
+
+    /// Runs the command against every targeted light at once. Each light reports its own
+    /// outcome, and the command fails if any of them did.
+    async fn run_all(self, config: Config) -> Result<(), Error> {
+        let lights = self.target().lights(&config)?;
+        let connection = self.target().connection.resolve(&config);
+        if let ElgatoLight::Rename { name: Some(_), .. } = &self {
+            if lights.len() > 1 {
+                return Err(Error::Usage(
+                    "Only one light can be given a new name at a time. Leave out the name to use each light's name from the config file".to_string(),
+                ));
+            }
+        }
+        let command = Arc::new(self);
+        let config = Arc::new(config);
+
+        let outcomes = {
+            let command = Arc::clone(&command);
+            ElgatoLight::fan_out(lights, connection, move |label, keylight| {
+                let command = Arc::clone(&command);
+                let config = Arc::clone(&config);
+                async move { command.run(&label, keylight, &config).await }
+            })
+            .await?
+        };
+
+        let settled = ElgatoLight::settle(outcomes)?;
+        let checked = settled.check();
+        let mut outputs = Vec::new();
+        for (name, address, output) in settled.successes {
+            match output {
+                Some(output) => outputs.push((name, address, output)),
+                None if settled.total > 1 => println!("{}: ok", name),
+                None => {}
+            }
+        }
+
+        if let Some(rendered) = command.render(outputs) {
+            println!("{}", rendered);
+        }
+
+        checked
+    }
+
+    /// Formats what the lights reported for the commands that read something. Nothing is
+    /// printed when every light failed, except an empty JSON array.
+    fn render(&self, outputs: Vec<(String, Address, Output)>) -> Option<String> {
+        let rendered = match self {
+            ElgatoLight::Status { format, .. } if format.prints(outputs.len()) => {
+                let reports = ElgatoLight::reports(outputs, |output| match output {
+                    Output::Status(snapshot) => Some(snapshot),
+                    _ => None,
+                });
+                status::render(*format, &reports)
+            }
+            ElgatoLight::Battery { format, .. } if format.prints(outputs.len()) => {
+                let reports = ElgatoLight::reports(outputs, |output| match output {
+                    Output::Battery(battery) => Some(battery),
+                    _ => None,
+                });
+                report::render(*format, &reports)
+            }
+            ElgatoLight::Info { format, .. } if format.prints(outputs.len()) => {
+                let reports = ElgatoLight::reports(outputs, |output| match output {
+                    Output::Info(info) => Some(info),
+                    _ => None,
+                });
+                report::render(*format, &reports)
+            }
+            _ => return None,
+        };
+        Some(rendered)
+    }
+
+    /// Labels the outputs of one kind as reports.
+    fn reports<T>(
+        outputs: Vec<(String, Address, Output)>,
+        reading: impl Fn(Output) -> Option<T>,
+    ) -> Vec<Report<T>> {
+        outputs
+            .into_iter()
+            .filter_map(|(name, address, output)| {
+                Some(Report::from((name, address, reading(output)?)))
+            })
+            .collect()
+    }
+
+    async fn run(
+        &self,
+        label: &str,
+        mut keylight: KeyLight,
+        config: &Config,
+    ) -> Result<Option<Output>, Error> {
+        if let Some(channels) = self.channels() {
+            keylight.select(channels.index())?;
+        }
+
         match self {
             ElgatoLight::On {
                 brightness,
                 temperature,
+                transition,
                 ..
             } => {
-                keylight.set_power(true).await?;
-                keylight.set_brightness(*brightness).await?;
-                keylight.set_temperature(*temperature).await?;
+                let brightness = brightness
+                    .or(config.on.brightness)
+                    .unwrap_or(config::DEFAULT_BRIGHTNESS);
+                let temperature = temperature
+                    .or(config.on.temperature)
+                    .unwrap_or(config::DEFAULT_TEMPERATURE);
+
+                ElgatoLight::turn_on(
+                    &mut keylight,
+                    Some(brightness),
+                    Some(temperature),
+                    *transition,
+                )
+                .await?;
+            }
+            ElgatoLight::Off { transition, .. } => {
+                ElgatoLight::turn_off(&mut keylight, *transition).await?;
             }
-            ElgatoLight::Off { .. } => {
-                keylight.set_power(false).await?;
+            ElgatoLight::Toggle {
+                brightness,
+                temperature,
+                transition,
+                ..
+            } => {
+                let lights = keylight.lights().await?;
+                if lights.iter().all(|light| light.on == 0) {
+                    let brightness = brightness.or(config.toggle.brightness);
+                    let temperature = temperature.or(config.toggle.temperature);
+                    ElgatoLight::turn_on(&mut keylight, brightness, temperature, *transition)
+                        .await?;
+                } else {
+                    ElgatoLight::turn_off(&mut keylight, *transition).await?;
+                }
             }
-            ElgatoLight::Brightness { brightness, .. } => {
+            ElgatoLight::Brightness {
+                brightness,
+                transition,
+                ..
+            } => {
                 ElgatoLight::ensure_light_on(&mut keylight).await?;
-                let status = keylight.get().await?;
-                let current_brightness = status.lights[0].brightness;
-                let new_brightness = ((current_brightness as i8) + *brightness).clamp(0, 100) as u8;
-                keylight.set_brightness(new_brightness).await?;
+                let new_brightness = |light: &Light| match brightness {
+                    BrightnessChange::Absolute(brightness) => *brightness,
+                    BrightnessChange::Relative(delta) => Brightness::clamped(
+                        (light.brightness as i16 + *delta as i16).clamp(0, 100) as u8,
+                    ),
+                };
+                keylight
+                    .change(
+                        |_, light| Change {
+                            brightness: Some(new_brightness(light)),
+                            ..Default::default()
+                        },
+                        *transition,
+                    )
+                    .await?;
             }
-            ElgatoLight::Temperature { temperature, .. } => {
+            ElgatoLight::Temperature {
+                temperature,
+                warmer,
+                cooler,
+                transition,
+                ..
+            } => {
                 ElgatoLight::ensure_light_on(&mut keylight).await?;
-                keylight.set_temperature(*temperature).await?;
+                let change = match temperature {
+                    Some(change) => *change,
+                    None if *warmer => TemperatureChange::Relative(-TEMPERATURE_STEP),
+                    None if *cooler => TemperatureChange::Relative(TEMPERATURE_STEP),
+                    None => unreachable!("clap requires a temperature, --warmer, or --cooler"),
+                };
+                let new_temperature = |light: &Light| match change {
+                    TemperatureChange::Absolute(temperature) => temperature,
+                    TemperatureChange::Relative(delta) => {
+                        let current_temperature = mired_to_kelvin(light.temperature);
+                        Kelvin::clamped((current_temperature as i32 + delta).max(0) as u32)
+                    }
+                };
+                keylight
+                    .change(
+                        |_, light| Change {
+                            temperature: Some(new_temperature(light)),
+                            ..Default::default()
+                        },
+                        *transition,
+                    )
+                    .await?;
             }
-            ElgatoLight::Status { .. } => {
+            ElgatoLight::Color {
+                color,
+                hue,
+                saturation,
+                brightness,
+                ..
+            } => {
+                let info = keylight.accessory_info().await?;
+                if !info.supports_color(&keylight.get().await?) {
+                    return Err(Error::Unsupported(format!(
+                        "{} ({}) only shows white light. Use temperature instead",
+                        label, info.product_name
+                    )));
+                }
+
+                let change = match color {
+                    Some(color) => Change {
+                        on: Some(true),
+                        brightness: Some(brightness.unwrap_or(color.brightness)),
+                        hue: Some(color.hue),
+                        saturation: Some(color.saturation),
+                        ..Default::default()
+                    },
+                    None => Change {
+                        on: Some(true),
+                        brightness: *brightness,
+                        hue: *hue,
+                        saturation: *saturation,
+                        ..Default::default()
+                    },
+                };
+                keylight.change(|_, _| change, None).await?;
+            }
+            ElgatoLight::Status { format, .. } => {
                 let status = keylight.get().await?;
-                println!("{:?}", status);
+                // The battery is extra detail here, so failing to read it leaves it out rather
+                // than failing the status.
+                let battery = match format {
+                    Format::Json => keylight.battery().await.ok().flatten(),
+                    _ => None,
+                };
+                return Ok(Some(Output::Status(status::Snapshot { status, battery })));
+            }
+            ElgatoLight::Battery { .. } => {
+                let battery = keylight.battery().await?.ok_or_else(|| {
+                    Error::Unsupported(format!("{} does not have a battery", label))
+                })?;
+                return Ok(Some(Output::Battery(battery)));
             }
+            ElgatoLight::Info { .. } => {
+                let info = keylight.accessory_info().await?;
+                return Ok(Some(Output::Info(info)));
+            }
+            ElgatoLight::Identify { .. } => keylight.identify().await?,
+            ElgatoLight::Rename { name, .. } => {
+                let name = match name {
+                    Some(name) => name.as_str(),
+                    None if config.lights.contains_key(label) => label,
+                    None => {
+                        return Err(Error::Usage(format!(
+                            "{} has no name in the config file. Give the new name to use",
+                            label
+                        )))
+                    }
+                };
+                if name.trim().is_empty() {
+                    return Err(Error::Usage("The new name cannot be empty".to_string()));
+                }
+                keylight.set_display_name(name).await?;
+            }
+            ElgatoLight::Discover { .. }
+            | ElgatoLight::Serve { .. }
+            | ElgatoLight::Mqtt { .. }
+            | ElgatoLight::Scene(_)
+            | ElgatoLight::Settings(_) => unreachable!("handled before targeting lights"),
         }
 
-        Ok(())
+        Ok(None)
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let args = ElgatoLight::from_args();
-    let ip_address = args.ip_address()?;
-    let keylight = ElgatoLight::get_keylight(ip_address).await?;
-    args.run(keylight).await?;
+async fn main() {
+    let args = match ElgatoLight::from_args_safe() {
+        Ok(args) => args,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e.message);
+            process::exit(Error::USAGE_EXIT_CODE);
+        }
+        Err(e) => e.exit(),
+    };
+
+    if let Err(e) = run(args).await {
+        eprintln!("Error: {}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+async fn run(args: ElgatoLight) -> Result<(), Error> {
+    let config = Config::load()?;
+    let discover_timeout = |timeout: Option<Duration>| {
+        timeout
+            .or(config.discover_timeout)
+            .unwrap_or(config::DEFAULT_DISCOVER_TIMEOUT)
+    };
+    match args {
+        ElgatoLight::Discover { timeout } => {
+            let connection = ConnectionOptions::default().resolve(&config);
+            ElgatoLight::discover(discover_timeout(timeout), connection).await
+        }
+        ElgatoLight::Identify {
+            all: true,
+            pause,
+            target,
+        } => {
+            let connection = target.connection.resolve(&config);
+            ElgatoLight::identify_all(discover_timeout(None), pause, connection).await
+        }
+        ElgatoLight::Status {
+            format,
+            watch: true,
+            interval,
+            exit_on_change,
+            target,
+        } => {
+            let interval = interval.unwrap_or(WATCH_INTERVAL);
+            ElgatoLight::watch(format, interval, exit_on_change, &target, &config).await
+        }
+        ElgatoLight::Serve { listen, connection } => {
+            let connection = connection.resolve(&config);
+            serve::serve(listen, config, connection).await
+        }
+        ElgatoLight::Mqtt {
+            broker,
+            username,
+            password,
+            topic_prefix,
+            discovery_prefix,
+            poll_interval,
+            connection,
+        } => {
+            let options = bridge::Options {
+                broker,
+                username,
+                password,
+                topic_prefix,
+                discovery_prefix,
+                poll_interval,
+                connection: connection.resolve(&config),
+            };
+            bridge::run(&config, options).await
+        }
+        ElgatoLight::Scene(command) => command.run(config).await,
+        ElgatoLight::Settings(command) => command.run(config).await,
+        args => args.run_all(config).await,
+    }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brightness(percent: u8) -> Brightness {
+        Brightness::try_from(i64::from(percent)).unwrap()
+    }
+
+    fn kelvin(kelvin: u32) -> Kelvin {
+        Kelvin::try_from(i64::from(kelvin)).unwrap()
+    }
+
+    #[test]
+    fn brightness_change_parses_absolute_and_relative() {
+        assert_eq!(
+            "=40".parse(),
+            Ok(BrightnessChange::Absolute(brightness(40)))
+        );
+        assert_eq!(
+            "=0".parse(),
+            Ok(BrightnessChange::Absolute(Brightness::MIN))
+        );
+        assert_eq!(
+            "=100".parse(),
+            Ok(BrightnessChange::Absolute(Brightness::MAX))
+        );
+        assert!("=101".parse::<BrightnessChange>().is_err());
+        assert_eq!("10".parse(), Ok(BrightnessChange::Relative(10)));
+        assert_eq!("100".parse(), Ok(BrightnessChange::Relative(100)));
+        assert!("101".parse::<BrightnessChange>().is_err());
+        assert_eq!("-100".parse(), Ok(BrightnessChange::Relative(-100)));
+        assert!("-101".parse::<BrightnessChange>().is_err());
+        assert!("brighter".parse::<BrightnessChange>().is_err());
+    }
+
+    #[test]
+    fn connection_prefers_flags_then_environment_then_config_then_defaults() {
+        let parse = |args: &[&str], config: &Config| {
+            let args = std::iter::once("elgato-light").chain(args.iter().copied());
+            ConnectionOptions::from_iter_safe(args)
+                .unwrap()
+                .resolve(config)
+        };
+        let config: Config = toml::from_str("timeout = \"7s\"\nretries = 4").unwrap();
+        std::env::remove_var("ELGATO_LIGHT_TIMEOUT");
+        std::env::remove_var("ELGATO_LIGHT_RETRIES");
+
+        let defaults = parse(&[], &Config::default());
+        assert_eq!(defaults.timeout, config::DEFAULT_TIMEOUT);
+        assert_eq!(defaults.retries, config::DEFAULT_RETRIES);
+
+        let from_config = parse(&[], &config);
+        assert_eq!(from_config.timeout, Duration::from_secs(7));
+        assert_eq!(from_config.retries, 4);
+
+        std::env::set_var("ELGATO_LIGHT_TIMEOUT", "3s");
+        std::env::set_var("ELGATO_LIGHT_RETRIES", "1");
+        let from_env = parse(&[], &config);
+        assert_eq!(from_env.timeout, Duration::from_secs(3));
+        assert_eq!(from_env.retries, 1);
+
+        let from_flags = parse(&["--timeout", "1s", "--retries", "0"], &config);
+        std::env::remove_var("ELGATO_LIGHT_TIMEOUT");
+        std::env::remove_var("ELGATO_LIGHT_RETRIES");
+        assert_eq!(from_flags.timeout, Duration::from_secs(1));
+        assert_eq!(from_flags.retries, 0);
+    }
+
+    #[test]
+    fn default_light_is_only_used_when_none_is_named() {
+        let config: Config = toml::from_str(
+            "ip_address = \"192.168.0.25\"\n[lights]\ndesk = \"192.168.0.30\"\n[groups]\noffice = [\"desk\", \"192.168.0.31\"]",
+        )
+        .unwrap();
+        let labels = |target: Target| -> Vec<String> {
+            target
+                .lights(&config)
+                .unwrap()
+                .into_iter()
+                .map(|(label, address)| format!("{} {}", label, address))
+                .collect()
+        };
+
+        assert_eq!(labels(Target::default()), ["192.168.0.25 192.168.0.25"]);
+        assert_eq!(
+            labels(Target {
+                light: vec!["desk".to_string()],
+                ..Default::default()
+            }),
+            ["desk 192.168.0.30"]
+        );
+        assert_eq!(
+            labels(Target {
+                ip_address: vec!["192.168.0.30".to_string()],
+                group: Some("office".to_string()),
+                ..Default::default()
+            }),
+            ["192.168.0.30 192.168.0.30", "192.168.0.31 192.168.0.31"]
+        );
+        assert!(matches!(
+            Target::default().lights(&Config::default()),
+            Err(Error::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn identify_all_cannot_be_combined_with_a_target() {
+        let parse = |args: &[&str]| {
+            let args = ["elgato-light", "identify"].iter().chain(args);
+            ElgatoLight::from_iter_safe(args)
+        };
+        assert!(parse(&["--all"]).is_ok());
+        assert!(parse(&["--ip-address", "192.168.0.25"]).is_ok());
+        for target in [
+            ["--ip-address", "192.168.0.25"],
+            ["--light", "desk"],
+            ["--group", "office"],
+        ] {
+            let error = parse(&["--all", target[0], target[1]]).unwrap_err();
+            assert_eq!(
+                error.kind,
+                structopt::clap::ErrorKind::ArgumentConflict,
+                "{:?}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn interval_must_be_longer_than_zero() {
+        assert_eq!(parse_interval("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_interval("250ms"), Ok(Duration::from_millis(250)));
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("soon").is_err());
+    }
+
+    #[test]
+    fn temperature_change_parses_absolute_and_relative() {
+        assert!("2899".parse::<TemperatureChange>().is_err());
+        assert_eq!("2900".parse(), Ok(TemperatureChange::Absolute(Kelvin::MIN)));
+        assert_eq!(
+            "5000".parse(),
+            Ok(TemperatureChange::Absolute(kelvin(5000)))
+        );
+        assert_eq!("7000".parse(), Ok(TemperatureChange::Absolute(Kelvin::MAX)));
+        assert!("7001".parse::<TemperatureChange>().is_err());
+        assert_eq!("+500".parse(), Ok(TemperatureChange::Relative(500)));
+        assert_eq!("-300".parse(), Ok(TemperatureChange::Relative(-300)));
+        assert!("+warm".parse::<TemperatureChange>().is_err());
+    }
 }
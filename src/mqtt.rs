@@ -0,0 +1,469 @@
+use crate::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+pub const PORT: u16 = 1883;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+const PINGRESP: u8 = 0xd0;
+
+/// A message published to, or received from, the broker. Everything is sent at QoS 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+/// How to introduce ourselves to the broker.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub keep_alive: Duration,
+    /// Published by the broker on our behalf if the connection drops without a goodbye.
+    pub will: Option<Message>,
+}
+
+/// A minimal MQTT 3.1.1 client: just enough to publish, subscribe, and stay connected.
+///
+/// Incoming messages are read on a task of their own and delivered through the receiver
+/// [`Client::connect`] returns, which yields an error and closes once the connection is lost.
+pub struct Client {
+    writer: OwnedWriteHalf,
+    next_packet_id: u16,
+    /// Set while a ping has not been answered yet.
+    awaiting_pong: Arc<AtomicBool>,
+}
+
+impl Client {
+    pub async fn connect(
+        broker: &str,
+        options: &Options,
+        timeout: Duration,
+    ) -> Result<(Client, mpsc::Receiver<Result<Message, Error>>), Error> {
+        let stream = tokio::time::timeout(timeout, TcpStream::connect(broker))
+            .await
+            .map_err(|_| Error::Mqtt(format!("timed out connecting to {}", broker)))?
+            .map_err(|e| Error::Mqtt(format!("cannot connect to {}: {}", broker, e)))?;
+        let (mut reader, writer) = stream.into_split();
+        let mut client = Client {
+            writer,
+            next_packet_id: 1,
+            awaiting_pong: Arc::new(AtomicBool::new(false)),
+        };
+
+        client.send(CONNECT, &connect_body(options)).await?;
+        let (header, body) = tokio::time::timeout(timeout, read_packet(&mut reader))
+            .await
+            .map_err(|_| Error::Mqtt(format!("{} did not answer the connection", broker)))??;
+        if header != CONNACK || body.len() != 2 {
+            return Err(Error::Mqtt(format!(
+                "{} answered the connection with something other than CONNACK",
+                broker
+            )));
+        }
+        if body[1] != 0 {
+            return Err(Error::Mqtt(format!(
+                "{} refused the connection: {}",
+                broker,
+                refusal(body[1])
+            )));
+        }
+
+        let (sender, receiver) = mpsc::channel(64);
+        tokio::spawn(read_messages(
+            reader,
+            sender,
+            Arc::clone(&client.awaiting_pong),
+        ));
+        Ok((client, receiver))
+    }
+
+    pub async fn publish(&mut self, message: &Message) -> Result<(), Error> {
+        let mut body = Vec::new();
+        put_string(&mut body, &message.topic);
+        body.extend_from_slice(&message.payload);
+        self.send(PUBLISH | u8::from(message.retain), &body).await
+    }
+
+    /// Subscribes to `filter` at QoS 0. The broker's acknowledgement is not waited for.
+    pub async fn subscribe(&mut self, filter: &str) -> Result<(), Error> {
+        let mut body = self.packet_id().to_be_bytes().to_vec();
+        put_string(&mut body, filter);
+        body.push(0);
+        self.send(SUBSCRIBE, &body).await
+    }
+
+    /// Lets the broker know we are still here when nothing else has been sent for a while.
+    /// Fails when the broker never answered the previous ping, since a connection that has
+    /// silently gone away can look open for a long time.
+    pub async fn ping(&mut self) -> Result<(), Error> {
+        if self.awaiting_pong.swap(true, Ordering::SeqCst) {
+            return Err(Error::Mqtt(
+                "the broker stopped answering pings".to_string(),
+            ));
+        }
+        self.send(PINGREQ, &[]).await
+    }
+
+    fn packet_id(&mut self) -> u16 {
+        let id = self.next_packet_id;
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    async fn send(&mut self, header: u8, body: &[u8]) -> Result<(), Error> {
+        let mut packet = vec![header];
+        put_length(&mut packet, body.len());
+        packet.extend_from_slice(body);
+        self.writer
+            .write_all(&packet)
+            .await
+            .map_err(|e| Error::Mqtt(format!("lost the connection to the broker: {}", e)))
+    }
+}
+
+fn connect_body(options: &Options) -> Vec<u8> {
+    let mut flags = 0x02; // Clean session.
+    if let Some(will) = &options.will {
+        flags |= 0x04;
+        if will.retain {
+            flags |= 0x20;
+        }
+    }
+    if options.username.is_some() {
+        flags |= 0x80;
+    }
+    if options.password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = Vec::new();
+    put_string(&mut body, "MQTT");
+    body.push(4); // Protocol level 3.1.1.
+    body.push(flags);
+    let keep_alive = options.keep_alive.as_secs().min(u64::from(u16::MAX)) as u16;
+    body.extend_from_slice(&keep_alive.to_be_bytes());
+
+    put_string(&mut body, &options.client_id);
+    if let Some(will) = &options.will {
+        put_string(&mut body, &will.topic);
+        put_bytes(&mut body, &will.payload);
+    }
+    if let Some(username) = &options.username {
+        put_string(&mut body, username);
+    }
+    if let Some(password) = &options.password {
+        put_string(&mut body, password);
+    }
+    body
+}
+
+fn refusal(code: u8) -> &'static str {
+    match code {
+        1 => "unsupported protocol version",
+        2 => "client identifier rejected",
+        3 => "server unavailable",
+        4 => "bad username or password",
+        5 => "not authorized",
+        _ => "unknown reason",
+    }
+}
+
+/// Forwards every message the broker sends until the connection closes.
+async fn read_messages(
+    mut reader: OwnedReadHalf,
+    sender: mpsc::Sender<Result<Message, Error>>,
+    awaiting_pong: Arc<AtomicBool>,
+) {
+    loop {
+        let (header, body) = match read_packet(&mut reader).await {
+            Ok(packet) => packet,
+            Err(e) => {
+                let _ = sender.send(Err(e)).await;
+                return;
+            }
+        };
+
+        match header & 0xf0 {
+            PUBLISH => match parse_publish(header, &body) {
+                Some(message) => {
+                    if sender.send(Ok(message)).await.is_err() {
+                        return;
+                    }
+                }
+                None => {
+                    let _ = sender
+                        .send(Err(Error::Mqtt(
+                            "the broker sent a malformed message".to_string(),
+                        )))
+                        .await;
+                    return;
+                }
+            },
+            SUBACK if body.get(2) == Some(&0x80) => {
+                let _ = sender
+                    .send(Err(Error::Mqtt(
+                        "the broker refused a subscription".to_string(),
+                    )))
+                    .await;
+                return;
+            }
+            PINGRESP => awaiting_pong.store(false, Ordering::SeqCst),
+            // Acknowledgements need no answer.
+            _ => {}
+        }
+    }
+}
+
+pub fn parse_publish(header: u8, body: &[u8]) -> Option<Message> {
+    let topic_length = usize::from(u16::from_be_bytes([*body.first()?, *body.get(1)?]));
+    let topic = std::str::from_utf8(body.get(2..2 + topic_length)?).ok()?;
+    let mut rest = 2 + topic_length;
+    if (header >> 1) & 0x03 > 0 {
+        // QoS 1 and 2 carry a packet identifier. The broker only sends them at QoS 0, since
+        // that is what we subscribe at, so it is skipped rather than acknowledged.
+        rest += 2;
+    }
+
+    Some(Message {
+        topic: topic.to_string(),
+        payload: body.get(rest..)?.to_vec(),
+        retain: header & 0x01 != 0,
+    })
+}
+
+pub async fn read_packet(reader: &mut (impl AsyncRead + Unpin)) -> Result<(u8, Vec<u8>), Error> {
+    let lost = |e: std::io::Error| Error::Mqtt(format!("lost the connection to the broker: {}", e));
+    let header = reader.read_u8().await.map_err(lost)?;
+
+    let mut length = 0;
+    for shift in (0..28).step_by(7) {
+        let byte = reader.read_u8().await.map_err(lost)?;
+        length |= usize::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.map_err(lost)?;
+            return Ok((header, body));
+        }
+    }
+
+    Err(Error::Mqtt(
+        "the broker sent a packet with an invalid length".to_string(),
+    ))
+}
+
+/// Writes the variable-length "remaining length" that follows every packet type.
+pub fn put_length(buffer: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        buffer.push(byte);
+        if length == 0 {
+            return;
+        }
+    }
+}
+
+fn put_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    let length = u16::try_from(bytes.len()).unwrap_or(u16::MAX);
+    buffer.extend_from_slice(&length.to_be_bytes());
+    buffer.extend_from_slice(&bytes[..usize::from(length)]);
+}
+
+pub fn put_string(buffer: &mut Vec<u8>, string: &str) {
+    put_bytes(buffer, string.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length(length: usize) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        put_length(&mut buffer, length);
+        buffer
+    }
+
+    fn options() -> Options {
+        Options {
+            client_id: "test".to_string(),
+            username: None,
+            password: None,
+            keep_alive: Duration::from_secs(60),
+            will: None,
+        }
+    }
+
+    /// Accepts one client and acknowledges its connection, answering its pings only when
+    /// `pong` is set.
+    async fn broker(pong: bool) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            while let Ok((header, _)) = read_packet(&mut stream).await {
+                match header {
+                    CONNECT => stream.write_all(&[CONNACK, 2, 0, 0]).await.unwrap(),
+                    PINGREQ if pong => stream.write_all(&[PINGRESP, 0]).await.unwrap(),
+                    _ => {}
+                }
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn answered_pings_keep_the_connection() {
+        let broker = broker(true).await;
+        let (mut client, _messages) = Client::connect(&broker, &options(), Duration::from_secs(5))
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            client.ping().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn an_unanswered_ping_loses_the_connection() {
+        let broker = broker(false).await;
+        let (mut client, _messages) = Client::connect(&broker, &options(), Duration::from_secs(5))
+            .await
+            .unwrap();
+        client.ping().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(matches!(client.ping().await, Err(Error::Mqtt(_))));
+    }
+
+    #[test]
+    fn remaining_length_uses_seven_bits_per_byte() {
+        assert_eq!(length(0), [0x00]);
+        assert_eq!(length(127), [0x7f]);
+        assert_eq!(length(128), [0x80, 0x01]);
+        assert_eq!(length(16_383), [0xff, 0x7f]);
+        assert_eq!(length(16_384), [0x80, 0x80, 0x01]);
+        assert_eq!(length(268_435_455), [0xff, 0xff, 0xff, 0x7f]);
+    }
+
+    #[tokio::test]
+    async fn packets_read_back_the_way_they_were_written() {
+        for size in [0, 1, 127, 128, 20_000] {
+            let body: Vec<u8> = (0..size).map(|i| i as u8).collect();
+            let mut packet = vec![PUBLISH];
+            put_length(&mut packet, body.len());
+            packet.extend_from_slice(&body);
+
+            let (header, read) = read_packet(&mut packet.as_slice()).await.unwrap();
+            assert_eq!(header, PUBLISH);
+            assert_eq!(read, body, "{} bytes", size);
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_packets_are_errors() {
+        // A length may not run past four bytes.
+        let mut too_long: &[u8] = &[PUBLISH, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(read_packet(&mut too_long).await.is_err());
+
+        let mut truncated: &[u8] = &[PUBLISH, 0x05, b'a', b'b'];
+        assert!(read_packet(&mut truncated).await.is_err());
+
+        let mut empty: &[u8] = &[];
+        assert!(read_packet(&mut empty).await.is_err());
+    }
+
+    #[test]
+    fn connect_asks_for_a_clean_session_and_nothing_else() {
+        let body = connect_body(&options());
+        assert_eq!(&body[..7], b"\x00\x04MQTT\x04");
+        assert_eq!(body[7], 0x02);
+        assert_eq!(&body[8..10], 60u16.to_be_bytes());
+        assert_eq!(&body[10..], b"\x00\x04test");
+    }
+
+    #[test]
+    fn connect_flags_follow_the_will_and_credentials() {
+        let will = Message {
+            topic: "elgato/bridge/availability".to_string(),
+            payload: b"offline".to_vec(),
+            retain: true,
+        };
+        let body = connect_body(&Options {
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            will: Some(will.clone()),
+            ..options()
+        });
+        // Username, password, will retain, will, and clean session.
+        assert_eq!(body[7], 0xe6);
+
+        let mut payload = Vec::new();
+        put_string(&mut payload, "test");
+        put_string(&mut payload, &will.topic);
+        put_bytes(&mut payload, &will.payload);
+        put_string(&mut payload, "user");
+        put_string(&mut payload, "secret");
+        assert_eq!(&body[10..], payload);
+
+        let body = connect_body(&Options {
+            will: Some(Message {
+                retain: false,
+                ..will
+            }),
+            ..options()
+        });
+        assert_eq!(body[7], 0x06);
+    }
+
+    #[test]
+    fn publish_is_parsed_with_its_retain_flag() {
+        let mut body = Vec::new();
+        put_string(&mut body, "elgato/desk/set");
+        body.extend_from_slice(br#"{"state":"ON"}"#);
+
+        assert_eq!(
+            parse_publish(PUBLISH | 0x01, &body),
+            Some(Message {
+                topic: "elgato/desk/set".to_string(),
+                payload: br#"{"state":"ON"}"#.to_vec(),
+                retain: true,
+            })
+        );
+        assert_eq!(parse_publish(PUBLISH, &body).map(|m| m.retain), Some(false));
+    }
+
+    #[test]
+    fn publish_at_qos_1_skips_the_packet_identifier() {
+        let mut body = Vec::new();
+        put_string(&mut body, "a/b");
+        body.extend_from_slice(&[0x00, 0x07]);
+        body.extend_from_slice(b"payload");
+
+        let message = parse_publish(PUBLISH | 0x02, &body).unwrap();
+        assert_eq!(message.topic, "a/b");
+        assert_eq!(message.payload, b"payload");
+    }
+
+    #[test]
+    fn malformed_publish_is_rejected() {
+        assert_eq!(parse_publish(PUBLISH, &[]), None);
+        assert_eq!(parse_publish(PUBLISH, &[0x00, 0x05, b'a']), None);
+        assert_eq!(parse_publish(PUBLISH, &[0x00, 0x02, 0xff, 0xfe]), None);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::address::Address;
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Table,
+}
+
+impl Format {
+    /// Whether anything is printed for this many reports. JSON always is, so that a run where
+    /// every light failed still prints an empty array.
+    pub fn prints(self, reports: usize) -> bool {
+        reports > 0 || self == Format::Json
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "table" => Ok(Format::Table),
+            _ => Err(format!("Unknown format '{}'. Use text, json, or table", s)),
+        }
+    }
+}
+
+/// What one targeted device reported, labelled the way the user asked for it.
+pub struct Report<T> {
+    pub name: String,
+    pub address: Address,
+    pub reading: T,
+}
+
+impl<T> From<(String, Address, T)> for Report<T> {
+    fn from((name, address, reading): (String, Address, T)) -> Self {
+        Report {
+            name,
+            address,
+            reading,
+        }
+    }
+}
+
+/// A reading shown as a block of lines in text, an object in JSON, and a single table row.
+pub trait Reading {
+    type Json<'a>: Serialize
+    where
+        Self: 'a;
+
+    fn lines(&self) -> Vec<String>;
+
+    /// The JSON fields that follow the device's `name` and `ip_address`.
+    fn json(&self) -> Self::Json<'_>;
+
+    /// The table header and row, without the NAME and ADDRESS columns.
+    fn header() -> String;
+    fn row(&self) -> String;
+}
+
+#[derive(Serialize)]
+struct DeviceJson<'a, T> {
+    name: &'a str,
+    ip_address: String,
+    #[serde(flatten)]
+    reading: T,
+}
+
+pub fn render<T: Reading>(format: Format, reports: &[Report<T>]) -> String {
+    match format {
+        Format::Text => render_text(reports),
+        Format::Json => render_json(reports),
+        Format::Table => render_table(reports),
+    }
+}
+
+fn render_text<T: Reading>(reports: &[Report<T>]) -> String {
+    let blocks: Vec<String> = reports
+        .iter()
+        .map(|report| {
+            let mut lines = Vec::new();
+            if reports.len() > 1 {
+                lines.push(format!("{}:", report.name));
+            }
+            lines.extend(report.reading.lines());
+            lines.join("\n")
+        })
+        .collect();
+
+    blocks.join("\n\n")
+}
+
+fn render_json<T: Reading>(reports: &[Report<T>]) -> String {
+    let devices: Vec<DeviceJson<T::Json<'_>>> = reports
+        .iter()
+        .map(|report| DeviceJson {
+            name: &report.name,
+            ip_address: report.address.to_string(),
+            reading: report.reading.json(),
+        })
+        .collect();
+
+    serde_json::to_string_pretty(&devices).expect("reports serialize to JSON")
+}
+
+fn render_table<T: Reading>(reports: &[Report<T>]) -> String {
+    let mut rows = vec![format!("{:<24} {:<15} {}", "NAME", "ADDRESS", T::header())];
+
+    for report in reports {
+        rows.push(format!(
+            "{:<24} {:<15} {}",
+            report.name,
+            report.address.to_string(),
+            report.reading.row()
+        ));
+    }
+
+    rows.join("\n")
+}
@@ -0,0 +1,215 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::keylight::{mired_to_kelvin, Change, Status};
+use crate::units::{Brightness, Kelvin};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// The look of a single light within a scene, with temperature in Kelvin so that the scenes
+/// file can be edited by hand.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LightState {
+    pub on: bool,
+    pub brightness: Brightness,
+    pub temperature: Kelvin,
+}
+
+impl LightState {
+    /// Reads the state of every light the device reports, in its order.
+    pub fn from_status(status: &Status) -> Vec<LightState> {
+        status
+            .lights
+            .iter()
+            .map(|light| LightState {
+                on: light.on != 0,
+                brightness: Brightness::clamped(light.brightness),
+                temperature: Kelvin::clamped(mired_to_kelvin(light.temperature)),
+            })
+            .collect()
+    }
+
+    /// The changes that restore this state, in order. A light that is on is turned on before
+    /// its look is set, and one that is off is turned off last, so it never shows the old look
+    /// at the new brightness or the new look while it should be dark.
+    pub fn changes(&self) -> [Change; 2] {
+        let look = Change {
+            brightness: Some(self.brightness),
+            temperature: Some(self.temperature),
+            ..Default::default()
+        };
+        let power = Change {
+            on: Some(self.on),
+            ..Default::default()
+        };
+        if self.on {
+            [power, look]
+        } else {
+            [look, power]
+        }
+    }
+}
+
+/// Light states keyed by the light's configured name or IP address, with one state for each
+/// light the device reports, in its order.
+pub type Scene = BTreeMap<String, Vec<LightState>>;
+
+/// Saved scenes, stored in `scenes.toml` next to the config file.
+#[derive(Debug, Default)]
+pub struct Scenes {
+    path: Option<PathBuf>,
+    scenes: BTreeMap<String, Scene>,
+}
+
+impl Scenes {
+    pub fn load() -> Result<Scenes, Error> {
+        Scenes::load_from(Config::path().map(|path| path.with_file_name("scenes.toml")))
+    }
+
+    fn load_from(path: Option<PathBuf>) -> Result<Scenes, Error> {
+        let scenes = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => toml::from_str(&contents).map_err(|e| {
+                    Error::Config(format!("Invalid scenes file {}: {}", path.display(), e))
+                })?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => {
+                    return Err(Error::Config(format!(
+                        "Unable to read scenes file {}: {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            },
+            None => BTreeMap::new(),
+        };
+
+        Ok(Scenes { path, scenes })
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Scene, Error> {
+        self.scenes
+            .get(name)
+            .ok_or_else(|| Error::Config(format!("Unknown scene '{}'", name)))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.scenes.keys().map(String::as_str)
+    }
+
+    pub fn save(&mut self, name: &str, scene: Scene) -> Result<(), Error> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            Error::Config("Unable to find a config directory to save scenes in".to_string())
+        })?;
+
+        self.scenes.insert(name.to_string(), scene);
+
+        let write_error = |e: &dyn std::fmt::Display| {
+            Error::Config(format!(
+                "Unable to write scenes file {}: {}",
+                path.display(),
+                e
+            ))
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| write_error(&e))?;
+        }
+        let contents = toml::to_string_pretty(&self.scenes).map_err(|e| write_error(&e))?;
+        fs::write(path, contents).map_err(|e| write_error(&e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn state(on: bool, brightness: u8, temperature: u32) -> LightState {
+        LightState {
+            on,
+            brightness: Brightness::clamped(brightness),
+            temperature: Kelvin::clamped(temperature),
+        }
+    }
+
+    #[test]
+    fn scenes_round_trip_through_the_scenes_file() {
+        let dir = env::temp_dir().join(format!("elgato-light-scenes-{}", std::process::id()));
+        let path = dir.join("scenes.toml");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut scenes = Scenes::load_from(Some(path.clone())).unwrap();
+        assert_eq!(scenes.names().count(), 0);
+        let recording: Scene = [
+            ("desk".to_string(), vec![state(true, 35, 4500)]),
+            (
+                "192.168.0.31".to_string(),
+                vec![state(false, 50, 2900), state(true, 80, 6500)],
+            ),
+        ]
+        .into();
+        scenes.save("recording", recording.clone()).unwrap();
+
+        let loaded = Scenes::load_from(Some(path)).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(loaded.names().collect::<Vec<_>>(), ["recording"]);
+        assert_eq!(loaded.get("recording").unwrap(), &recording);
+        assert!(matches!(loaded.get("streaming"), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn out_of_range_states_are_rejected() {
+        let scene = |state: &str| format!("[[recording.desk]]\n{}", state);
+        let valid = scene("on = true\nbrightness = 35\ntemperature = 4500");
+        assert!(toml::from_str::<BTreeMap<String, Scene>>(&valid).is_ok());
+
+        for bad in [
+            "on = true\nbrightness = 101\ntemperature = 4500",
+            "on = true\nbrightness = 35\ntemperature = 2899",
+            "on = true\nbrightness = 35\ntemperature = 7001",
+            "on = true\nbrightness = 35",
+            "on = true\nbrightness = 35\ntemperature = 4500\nhue = 30",
+        ] {
+            assert!(
+                toml::from_str::<BTreeMap<String, Scene>>(&scene(bad)).is_err(),
+                "{:?} was accepted",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn every_light_of_a_device_is_recorded() {
+        let status: Status = serde_json::from_value(serde_json::json!({
+            "numberOfLights": 2,
+            "lights": [
+                { "on": 1, "brightness": 35, "temperature": 222 },
+                { "on": 0, "brightness": 80, "temperature": 344 },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            LightState::from_status(&status),
+            [state(true, 35, 4500), state(false, 80, 2900)]
+        );
+    }
+
+    #[test]
+    fn power_comes_first_when_on_and_last_when_off() {
+        let [first, second] = state(true, 35, 4500).changes();
+        assert_eq!(first.on, Some(true));
+        assert_eq!((first.brightness, first.temperature), (None, None));
+        assert_eq!(second.on, None);
+        assert_eq!(second.brightness, Some(Brightness::clamped(35)));
+        assert_eq!(second.temperature, Some(Kelvin::clamped(4500)));
+
+        let [first, second] = state(false, 50, 2900).changes();
+        assert_eq!(first.on, None);
+        assert_eq!(first.brightness, Some(Brightness::clamped(50)));
+        assert_eq!(first.temperature, Some(Kelvin::MIN));
+        assert_eq!(second.on, Some(false));
+        assert_eq!((second.brightness, second.temperature), (None, None));
+    }
+}
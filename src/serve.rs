@@ -0,0 +1,510 @@
+use crate::address::Address;
+use crate::config::Config;
+use crate::error::Error;
+use crate::keylight::{Change, Connection, KeyLight};
+use crate::report::Report;
+use crate::status::{self, Snapshot};
+use crate::units::{Brightness, Kelvin};
+use crate::{Channels, ElgatoLight, Target};
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The largest request body read. Light requests are a handful of small fields.
+const MAX_BODY: usize = 16 * 1024;
+
+/// What every request handler needs: the config the lights are named in, and how to reach them.
+struct State {
+    config: Config,
+    connection: Connection,
+}
+
+/// The body of `POST /lights/{name}/{action}` and `PATCH /lights/{name}`. Every field is
+/// optional, and the body can be left out entirely.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct LightRequest {
+    on: Option<bool>,
+    brightness: Option<Brightness>,
+    temperature: Option<Kelvin>,
+    transition: Option<String>,
+    index: Option<usize>,
+}
+
+impl LightRequest {
+    fn parse(body: &[u8]) -> Result<LightRequest, Error> {
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(LightRequest::default());
+        }
+        serde_json::from_slice(body)
+            .map_err(|e| Error::Usage(format!("Invalid request body: {}", e)))
+    }
+
+    fn transition(&self) -> Result<Option<Duration>, Error> {
+        self.transition
+            .as_deref()
+            .map(|transition| {
+                humantime::parse_duration(transition).map_err(|e| {
+                    Error::Usage(format!("Invalid transition '{}': {}", transition, e))
+                })
+            })
+            .transpose()
+    }
+
+    fn channels(&self) -> Channels {
+        Channels {
+            index: self.index,
+            all_channels: false,
+        }
+    }
+}
+
+/// Why a request was not answered with a light's status.
+#[derive(Debug)]
+enum Rejection {
+    /// No endpoint has this path.
+    NotFound(String),
+    /// The path exists, but takes only the methods in `allow`.
+    MethodNotAllowed {
+        allow: &'static str,
+    },
+    PayloadTooLarge,
+    Failed(Error),
+}
+
+impl From<Error> for Rejection {
+    fn from(e: Error) -> Self {
+        Rejection::Failed(e)
+    }
+}
+
+/// Serves the light controls over HTTP until the process is stopped. Lights are addressed by
+/// their name in the config file, or by IP address or hostname.
+pub async fn serve(
+    listen: SocketAddr,
+    config: Config,
+    connection: Connection,
+) -> Result<(), Error> {
+    let state = Arc::new(State { config, connection });
+    let make_service = make_service_fn(move |_| {
+        let state = Arc::clone(&state);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(Arc::clone(&state), request)
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&listen)
+        .map_err(|e| Error::Server(format!("cannot listen on {}: {}", listen, e)))?
+        .serve(make_service);
+    eprintln!("Listening on http://{}", listen);
+    server.await.map_err(|e| Error::Server(e.to_string()))
+}
+
+async fn handle(state: Arc<State>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let result = match read_body(request.into_body()).await {
+        Ok(body) => route(&state, &method, &path, &body).await,
+        Err(rejection) => Err(rejection),
+    };
+
+    let mut response = Response::builder().header("Content-Type", "application/json");
+    let (status, body) = match result {
+        Ok(body) => (StatusCode::OK, body),
+        Err(Rejection::NotFound(message)) => (
+            StatusCode::NOT_FOUND,
+            serde_json::json!({ "error": message }),
+        ),
+        Err(Rejection::MethodNotAllowed { allow }) => {
+            response = response.header("Allow", allow);
+            (
+                StatusCode::METHOD_NOT_ALLOWED,
+                serde_json::json!({ "error": format!("{} {} is not supported. Use {}", method, path, allow) }),
+            )
+        }
+        Err(Rejection::PayloadTooLarge) => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            serde_json::json!({ "error": format!("Request bodies are limited to {} bytes", MAX_BODY) }),
+        ),
+        Err(Rejection::Failed(e)) => (
+            status_code(&e),
+            serde_json::json!({ "error": e.to_string() }),
+        ),
+    };
+    eprintln!("{} {} {}", method, path, status.as_u16());
+
+    let response = response
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string_pretty(&body).expect("responses serialize to JSON"),
+        ))
+        .expect("responses are well-formed");
+    Ok(response)
+}
+
+/// Reads a request body, refusing one longer than `MAX_BODY` before all of it has arrived.
+async fn read_body(mut body: Body) -> Result<Vec<u8>, Rejection> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk =
+            chunk.map_err(|e| Error::Usage(format!("Could not read the request body: {}", e)))?;
+        if bytes.len() + chunk.len() > MAX_BODY {
+            return Err(Rejection::PayloadTooLarge);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+async fn route(
+    state: &State,
+    method: &Method,
+    path: &str,
+    body: &[u8],
+) -> Result<serde_json::Value, Rejection> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        (&Method::GET, ["lights"]) => Ok(list(state).await?),
+        (&Method::GET, ["lights", name]) => Ok(show(state, name).await?),
+        (&Method::PATCH, ["lights", name]) => {
+            Ok(update(state, name, LightRequest::parse(body)?).await?)
+        }
+        (&Method::POST, ["lights", name, action]) => {
+            act(state, name, action, LightRequest::parse(body)?).await
+        }
+        (_, ["lights"]) => Err(Rejection::MethodNotAllowed { allow: "GET" }),
+        (_, ["lights", _]) => Err(Rejection::MethodNotAllowed {
+            allow: "GET, PATCH",
+        }),
+        (_, ["lights", _, _]) => Err(Rejection::MethodNotAllowed { allow: "POST" }),
+        _ => Err(Rejection::NotFound(format!("No endpoint at {}", path))),
+    }
+}
+
+/// `GET /lights`: the status of every light in the config file, in the same shape as
+/// `status --format json`. Lights that cannot be read carry an `error` instead.
+async fn list(state: &State) -> Result<serde_json::Value, Error> {
+    let mut names: Vec<&str> = state.config.lights.keys().map(String::as_str).collect();
+    if names.is_empty() {
+        names.extend(state.config.ip_address.as_deref());
+    }
+
+    let lights = names
+        .into_iter()
+        .map(|name| Ok((name.to_string(), address(state, name)?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let outcomes = ElgatoLight::fan_out(lights, state.connection, |_, keylight| async move {
+        read(&keylight).await
+    })
+    .await?;
+
+    let devices = outcomes
+        .into_iter()
+        .map(|(name, address, result)| match result {
+            Ok(snapshot) => status::json(&Report::from((name, address, snapshot))),
+            Err(e) => serde_json::json!({
+                "name": name,
+                "ip_address": address.to_string(),
+                "error": e.to_string(),
+            }),
+        })
+        .collect();
+    Ok(serde_json::Value::Array(devices))
+}
+
+/// `GET /lights/{name}`: the status of one light.
+async fn show(state: &State, name: &str) -> Result<serde_json::Value, Error> {
+    let address = address(state, name)?;
+    let keylight = KeyLight::new(&address, state.connection).await?;
+    report(name, address, &keylight).await
+}
+
+/// `PATCH /lights/{name}`: changes only what the body gives, leaving the rest of the light as
+/// it is, and answers with the light's new status.
+async fn update(
+    state: &State,
+    name: &str,
+    request: LightRequest,
+) -> Result<serde_json::Value, Error> {
+    let change = Change {
+        on: request.on,
+        brightness: request.brightness,
+        temperature: request.temperature,
+        ..Default::default()
+    };
+    if change == Change::default() {
+        return Err(Error::Usage(
+            "Give at least one of on, brightness, or temperature to change".to_string(),
+        ));
+    }
+    let transition = request.transition()?;
+
+    let address = address(state, name)?;
+    let mut keylight = KeyLight::new(&address, state.connection).await?;
+    keylight.select(request.index)?;
+    keylight.change(|_, _| change, transition).await?;
+    keylight.select(None)?;
+    report(name, address, &keylight).await
+}
+
+/// `POST /lights/{name}/{action}`: runs `on`, `off`, `toggle`, or `identify` the way the
+/// command of the same name does, and answers with the light's new status.
+async fn act(
+    state: &State,
+    name: &str,
+    action: &str,
+    request: LightRequest,
+) -> Result<serde_json::Value, Rejection> {
+    let transition = request.transition()?;
+    let command = match action {
+        "on" => ElgatoLight::On {
+            brightness: request.brightness,
+            temperature: request.temperature,
+            transition,
+            channels: request.channels(),
+            target: Target::default(),
+        },
+        "off" => ElgatoLight::Off {
+            transition,
+            channels: request.channels(),
+            target: Target::default(),
+        },
+        "toggle" => ElgatoLight::Toggle {
+            brightness: request.brightness,
+            temperature: request.temperature,
+            transition,
+            channels: request.channels(),
+            target: Target::default(),
+        },
+        "identify" => ElgatoLight::Identify {
+            all: false,
+            pause: Duration::ZERO,
+            target: Target::default(),
+        },
+        _ => {
+            return Err(Rejection::NotFound(format!(
+                "Unknown action '{}'. Use on, off, toggle, or identify",
+                action
+            )))
+        }
+    };
+
+    let address = address(state, name)?;
+    let keylight = KeyLight::new(&address, state.connection).await?;
+    command.run(name, keylight, &state.config).await?;
+
+    let keylight = KeyLight::new(&address, state.connection).await?;
+    Ok(report(name, address, &keylight).await?)
+}
+
+/// Finds the light a request names: a light from the config file, or an IP address.
+fn address(state: &State, name: &str) -> Result<Address, Error> {
+    let configured =
+        state.config.lights.contains_key(name) || state.config.ip_address.as_deref() == Some(name);
+    let ip = IpAddr::from_str(name).is_ok() || SocketAddr::from_str(name).is_ok();
+    if !configured && !ip {
+        return Err(Error::Config(format!(
+            "No light named '{}' in the config file",
+            name
+        )));
+    }
+
+    Address::from_str(state.config.address(name))
+        .map_err(|e| Error::InvalidAddress(format!("Invalid address for {}: {}", name, e)))
+}
+
+/// Reads a light's status, and its battery when it can, as `status --format json` does.
+async fn read(keylight: &KeyLight) -> Result<Snapshot, Error> {
+    Ok(Snapshot {
+        status: keylight.get().await?,
+        battery: keylight.battery().await.ok().flatten(),
+    })
+}
+
+async fn report(
+    name: &str,
+    address: Address,
+    keylight: &KeyLight,
+) -> Result<serde_json::Value, Error> {
+    let snapshot = read(keylight).await?;
+    Ok(status::json(&Report::from((
+        name.to_string(),
+        address,
+        snapshot,
+    ))))
+}
+
+/// The HTTP status that best describes an error: the client's fault, an unknown light, or a
+/// light that did not cooperate.
+fn status_code(e: &Error) -> StatusCode {
+    match e {
+        Error::Usage(_) => StatusCode::BAD_REQUEST,
+        Error::Config(_) | Error::InvalidAddress(_) => StatusCode::NOT_FOUND,
+        Error::Unsupported(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        Error::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        Error::Unreachable(_) | Error::Http { .. } | Error::MalformedResponse(_) => {
+            StatusCode::BAD_GATEWAY
+        }
+        Error::Discovery(_) | Error::Server(_) | Error::Mqtt(_) | Error::LightsFailed { .. } => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fake_light;
+    use serde_json::{json, Value};
+
+    async fn state() -> Arc<State> {
+        let light = fake_light().await;
+        Arc::new(State {
+            config: toml::from_str(&format!("[lights]\ndesk = \"{}\"", light)).unwrap(),
+            connection: Connection {
+                timeout: Duration::from_secs(5),
+                retries: 0,
+            },
+        })
+    }
+
+    async fn send(
+        state: &Arc<State>,
+        method: Method,
+        path: &str,
+        body: impl Into<Body>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(body.into())
+            .unwrap();
+        let response = handle(Arc::clone(state), request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn lists_every_configured_light() {
+        let state = state().await;
+        let (status, body) = send(&state, Method::GET, "/lights", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["name"], "desk");
+        assert_eq!(
+            body[0]["lights"],
+            json!([{
+                "index": 0,
+                "on": true,
+                "brightness": 35,
+                "temperature": { "kelvin": 4500, "mired": 222 },
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn actions_and_changes_answer_with_the_new_status() {
+        let state = state().await;
+        let (status, body) = send(&state, Method::POST, "/lights/desk/off", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["lights"][0]["on"], false);
+
+        let (status, body) = send(
+            &state,
+            Method::POST,
+            "/lights/desk/on",
+            r#"{"brightness": 60}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["lights"][0]["on"], true);
+        assert_eq!(body["lights"][0]["brightness"], 60);
+
+        let (status, body) = send(
+            &state,
+            Method::PATCH,
+            "/lights/desk",
+            r#"{"temperature": 2900, "index": 0}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["lights"][0]["brightness"], 60);
+        assert_eq!(body["lights"][0]["temperature"]["kelvin"], 2900);
+
+        let (status, body) = send(
+            &state,
+            Method::PATCH,
+            "/lights/desk",
+            r#"{"on": false, "index": 1}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("no light at index 1"));
+    }
+
+    #[tokio::test]
+    async fn unknown_lights_and_routes_are_not_found() {
+        let state = state().await;
+        for (method, path) in [
+            (Method::GET, "/lights/garage"),
+            (Method::POST, "/lights/desk/dance"),
+            (Method::GET, "/status"),
+        ] {
+            let (status, body) = send(&state, method.clone(), path, "").await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", method, path);
+            assert!(body["error"].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn other_methods_are_not_allowed() {
+        let state = state().await;
+        for (method, path, allow) in [
+            (Method::DELETE, "/lights/desk", "GET, PATCH"),
+            (Method::POST, "/lights", "GET"),
+            (Method::GET, "/lights/desk/on", "POST"),
+        ] {
+            let request = Request::builder()
+                .method(method.clone())
+                .uri(path)
+                .body(Body::empty())
+                .unwrap();
+            let response = handle(Arc::clone(&state), request).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{} {}",
+                method,
+                path
+            );
+            assert_eq!(response.headers()["Allow"], allow);
+        }
+    }
+
+    #[tokio::test]
+    async fn bad_and_oversized_bodies_are_refused() {
+        let state = state().await;
+        let (status, _) = send(&state, Method::PATCH, "/lights/desk", r#"{"hue": 30}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(
+            &state,
+            Method::PATCH,
+            "/lights/desk",
+            vec![b' '; MAX_BODY + 1],
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
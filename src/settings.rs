@@ -0,0 +1,268 @@
+use crate::battery::BatterySettings;
+use crate::keylight::{kelvin_to_mired, mired_to_kelvin};
+use crate::report::Reading;
+use crate::units::{Brightness, Kelvin};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The settings a light keeps across power cycles, read from and written to
+/// `/elgato/lights/settings`. Fields this tool does not change, such as the power-on hue of
+/// color lights and the eco mode settings of battery-powered lights, are kept exactly as the
+/// light sent them and written back the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_on_behavior: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_on_brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_on_temperature: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch_on_duration_ms: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch_off_duration_ms: Option<u32>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Settings {
+    /// Reads the eco mode settings of battery-powered lights, or `None` for other lights.
+    pub fn battery(&self) -> Option<Result<BatterySettings, serde_json::Error>> {
+        self.other.get("battery").map(BatterySettings::deserialize)
+    }
+}
+
+/// What a light does when it gets power back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerOnBehavior {
+    /// Come back the way it was when power was lost.
+    Restore,
+    /// Come back at the power-on brightness and temperature.
+    Fixed,
+}
+
+impl PowerOnBehavior {
+    const RESTORE: u8 = 1;
+    const FIXED: u8 = 2;
+
+    pub fn from_device(value: u8) -> Option<PowerOnBehavior> {
+        match value {
+            PowerOnBehavior::RESTORE => Some(PowerOnBehavior::Restore),
+            PowerOnBehavior::FIXED => Some(PowerOnBehavior::Fixed),
+            _ => None,
+        }
+    }
+
+    pub fn to_device(self) -> u8 {
+        match self {
+            PowerOnBehavior::Restore => PowerOnBehavior::RESTORE,
+            PowerOnBehavior::Fixed => PowerOnBehavior::FIXED,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PowerOnBehavior::Restore => "restore",
+            PowerOnBehavior::Fixed => "fixed",
+        }
+    }
+}
+
+impl FromStr for PowerOnBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "restore" => Ok(PowerOnBehavior::Restore),
+            "fixed" => Ok(PowerOnBehavior::Fixed),
+            _ => Err(format!(
+                "Unknown power-on behavior '{}'. Use restore or fixed",
+                s
+            )),
+        }
+    }
+}
+
+/// Changes to make to a light's settings. Values left as `None` are kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SettingsChange {
+    pub power_on_behavior: Option<PowerOnBehavior>,
+    pub power_on_brightness: Option<Brightness>,
+    pub power_on_temperature: Option<Kelvin>,
+    pub switch_on_duration: Option<Duration>,
+    pub switch_off_duration: Option<Duration>,
+}
+
+impl SettingsChange {
+    pub fn is_empty(&self) -> bool {
+        self.power_on_behavior.is_none()
+            && self.power_on_brightness.is_none()
+            && self.power_on_temperature.is_none()
+            && self.switch_on_duration.is_none()
+            && self.switch_off_duration.is_none()
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(behavior) = self.power_on_behavior {
+            settings.power_on_behavior = Some(behavior.to_device());
+        }
+        if let Some(brightness) = self.power_on_brightness {
+            settings.power_on_brightness = Some(brightness.get());
+        }
+        if let Some(temperature) = self.power_on_temperature {
+            settings.power_on_temperature = Some(kelvin_to_mired(temperature));
+        }
+        if let Some(duration) = self.switch_on_duration {
+            settings.switch_on_duration_ms = Some(duration_ms(duration));
+        }
+        if let Some(duration) = self.switch_off_duration {
+            settings.switch_off_duration_ms = Some(duration_ms(duration));
+        }
+    }
+}
+
+fn duration_ms(duration: Duration) -> u32 {
+    duration.as_millis().try_into().unwrap_or(u32::MAX)
+}
+
+#[derive(Serialize)]
+pub struct SettingsJson {
+    power_on_behavior: Option<&'static str>,
+    power_on_brightness: Option<u8>,
+    power_on_temperature: Option<TemperatureJson>,
+    switch_on_duration_ms: Option<u32>,
+    switch_off_duration_ms: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct TemperatureJson {
+    kelvin: u32,
+    mired: u16,
+}
+
+fn behavior(settings: &Settings) -> String {
+    match settings.power_on_behavior {
+        Some(value) => match PowerOnBehavior::from_device(value) {
+            Some(behavior) => behavior.name().to_string(),
+            None => format!("unknown ({})", value),
+        },
+        None => "-".to_string(),
+    }
+}
+
+fn brightness(settings: &Settings) -> String {
+    settings
+        .power_on_brightness
+        .map_or("-".to_string(), |brightness| format!("{}%", brightness))
+}
+
+fn temperature(settings: &Settings) -> String {
+    settings
+        .power_on_temperature
+        .map_or("-".to_string(), |mired| {
+            format!("{} K", mired_to_kelvin(mired))
+        })
+}
+
+fn milliseconds(value: Option<u32>) -> String {
+    value.map_or("-".to_string(), |ms| format!("{}ms", ms))
+}
+
+impl Reading for Settings {
+    type Json<'a> = SettingsJson;
+
+    fn lines(&self) -> Vec<String> {
+        vec![
+            format!("Power on:             {}", behavior(self)),
+            format!("Power-on brightness:  {}", brightness(self)),
+            format!("Power-on temperature: {}", temperature(self)),
+            format!(
+                "Switch-on duration:   {}",
+                milliseconds(self.switch_on_duration_ms)
+            ),
+            format!(
+                "Switch-off duration:  {}",
+                milliseconds(self.switch_off_duration_ms)
+            ),
+        ]
+    }
+
+    fn json(&self) -> SettingsJson {
+        SettingsJson {
+            power_on_behavior: self
+                .power_on_behavior
+                .and_then(PowerOnBehavior::from_device)
+                .map(PowerOnBehavior::name),
+            power_on_brightness: self.power_on_brightness,
+            power_on_temperature: self.power_on_temperature.map(|mired| TemperatureJson {
+                kelvin: mired_to_kelvin(mired),
+                mired,
+            }),
+            switch_on_duration_ms: self.switch_on_duration_ms,
+            switch_off_duration_ms: self.switch_off_duration_ms,
+        }
+    }
+
+    fn header() -> String {
+        format!(
+            "{:<8} {:<10} {:<11} {:<9} SWITCH OFF",
+            "POWER ON", "BRIGHTNESS", "TEMPERATURE", "SWITCH ON"
+        )
+    }
+
+    fn row(&self) -> String {
+        format!(
+            "{:<8} {:<10} {:<11} {:<9} {}",
+            behavior(self),
+            brightness(self),
+            temperature(self),
+            milliseconds(self.switch_on_duration_ms),
+            milliseconds(self.switch_off_duration_ms)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Key Light Mini's settings, with fields this tool does not change.
+    const KEY_LIGHT_MINI: &str = r#"{"powerOnBehavior":1,"powerOnBrightness":20,"powerOnTemperature":213,"switchOnDurationMs":100,"switchOffDurationMs":300,"colorChangeDurationMs":100,"battery":{"energySaving":{"enable":1,"minimumBatteryLevel":15,"disableWifi":0,"adjustBrightness":{"enable":1,"brightness":10},"extra":"kept"},"bypass":0}}"#;
+
+    #[test]
+    fn settings_are_written_back_as_the_light_sent_them() {
+        let mut settings: Settings = serde_json::from_str(KEY_LIGHT_MINI).unwrap();
+        SettingsChange::default().apply(&mut settings);
+        assert_eq!(serde_json::to_string(&settings).unwrap(), KEY_LIGHT_MINI);
+
+        SettingsChange {
+            power_on_behavior: Some(PowerOnBehavior::Fixed),
+            power_on_brightness: Some(Brightness::clamped(40)),
+            ..Default::default()
+        }
+        .apply(&mut settings);
+        assert_eq!(
+            serde_json::to_string(&settings).unwrap(),
+            KEY_LIGHT_MINI
+                .replace(r#""powerOnBehavior":1"#, r#""powerOnBehavior":2"#)
+                .replace(r#""powerOnBrightness":20"#, r#""powerOnBrightness":40"#)
+        );
+    }
+
+    #[test]
+    fn battery_settings_are_read_for_display() {
+        let settings: Settings = serde_json::from_str(KEY_LIGHT_MINI).unwrap();
+        let battery = settings.battery().unwrap().unwrap();
+        assert_eq!(battery.energy_saving.enable, 1);
+        assert_eq!(battery.energy_saving.minimum_battery_level, 15.0);
+        assert_eq!(battery.energy_saving.adjust_brightness.brightness, 10.0);
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"powerOnBehavior":1,"battery":{"energySaving":"on"}}"#)
+                .unwrap();
+        assert!(settings.battery().unwrap().is_err());
+        assert!(Settings::default().battery().is_none());
+    }
+}
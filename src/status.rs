@@ -0,0 +1,306 @@
+use crate::battery::{Battery, BatteryJson};
+use crate::keylight::{mired_to_kelvin, Light, Status};
+use crate::report::{self, Format};
+use serde::Serialize;
+
+/// What `status` reads from one device.
+pub struct Snapshot {
+    pub status: Status,
+    /// Only read for JSON output, and `None` for lights without a battery.
+    pub battery: Option<Battery>,
+}
+
+pub type Report = report::Report<Snapshot>;
+
+#[derive(Serialize)]
+struct DeviceJson<'a> {
+    name: &'a str,
+    ip_address: String,
+    lights: Vec<LightJson>,
+    battery: Option<BatteryJson>,
+}
+
+#[derive(Serialize)]
+struct LightJson {
+    index: usize,
+    on: bool,
+    brightness: u8,
+    /// `null` while a color light shows a color instead of white.
+    temperature: Option<TemperatureJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<ColorJson>,
+}
+
+#[derive(Serialize)]
+struct TemperatureJson {
+    kelvin: u32,
+    mired: u16,
+}
+
+#[derive(Serialize)]
+struct ColorJson {
+    hue: f64,
+    saturation: f64,
+}
+
+pub fn render(format: Format, reports: &[Report]) -> String {
+    match format {
+        Format::Text => render_text(reports, reports.len() > 1),
+        Format::Json => render_json(reports),
+        Format::Table => render_table(reports, true),
+    }
+}
+
+/// Renders the devices that changed in one round of `status --watch`. Text is labelled when
+/// several devices are watched, JSON is one compact object per line, and the table header
+/// only comes with the first round.
+pub fn render_update(format: Format, reports: &[Report], labelled: bool, first: bool) -> String {
+    match format {
+        Format::Text => render_text(reports, labelled),
+        Format::Json => reports
+            .iter()
+            .map(|report| {
+                serde_json::to_string(&device_json(report)).expect("status serializes to JSON")
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Format::Table => render_table(reports, first),
+    }
+}
+
+/// The temperature of a white light, or the hue and saturation of a color light showing a color.
+fn shade(light: &Light) -> String {
+    match light.hue {
+        Some(hue) => format!(
+            "hue {}°, {}% saturation",
+            hue.round(),
+            light.saturation.unwrap_or_default().round()
+        ),
+        None => format!("{} K", mired_to_kelvin(light.temperature)),
+    }
+}
+
+fn render_text(reports: &[Report], labelled: bool) -> String {
+    let mut lines = Vec::new();
+    for report in reports {
+        let channels = report.reading.status.lights.len();
+        for (index, light) in report.reading.status.lights.iter().enumerate() {
+            let label = match (labelled, channels) {
+                (false, 1) => String::new(),
+                (false, _) => format!("Light {}: ", index),
+                (true, 1) => format!("{}: ", report.name),
+                (true, _) => format!("{} light {}: ", report.name, index),
+            };
+            lines.push(format!(
+                "{}{}, {}% brightness, {}",
+                label,
+                if light.on != 0 { "On" } else { "Off" },
+                light.brightness,
+                shade(light)
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+/// The JSON for one device, as it appears in the `--format json` array.
+pub fn json(report: &Report) -> serde_json::Value {
+    serde_json::to_value(device_json(report)).expect("status serializes to JSON")
+}
+
+fn device_json(report: &Report) -> DeviceJson<'_> {
+    DeviceJson {
+        name: &report.name,
+        ip_address: report.address.to_string(),
+        lights: report
+            .reading
+            .status
+            .lights
+            .iter()
+            .enumerate()
+            .map(|(index, light)| LightJson {
+                index,
+                on: light.on != 0,
+                brightness: light.brightness,
+                temperature: light.hue.is_none().then(|| TemperatureJson {
+                    kelvin: mired_to_kelvin(light.temperature),
+                    mired: light.temperature,
+                }),
+                color: light.hue.map(|hue| ColorJson {
+                    hue,
+                    saturation: light.saturation.unwrap_or_default(),
+                }),
+            })
+            .collect(),
+        battery: report.reading.battery.as_ref().map(BatteryJson::new),
+    }
+}
+
+fn render_json(reports: &[Report]) -> String {
+    let devices: Vec<DeviceJson> = reports.iter().map(device_json).collect();
+
+    serde_json::to_string_pretty(&devices).expect("status serializes to JSON")
+}
+
+fn render_table(reports: &[Report], header: bool) -> String {
+    let mut rows = Vec::new();
+    if header {
+        rows.push(format!(
+            "{:<24} {:<15} {:<5} {:<5} {:<10} TEMPERATURE",
+            "NAME", "ADDRESS", "INDEX", "POWER", "BRIGHTNESS"
+        ));
+    }
+
+    for report in reports {
+        for (index, light) in report.reading.status.lights.iter().enumerate() {
+            rows.push(format!(
+                "{:<24} {:<15} {:<5} {:<5} {:<10} {}",
+                report.name,
+                report.address.to_string(),
+                index,
+                if light.on != 0 { "on" } else { "off" },
+                format!("{}%", light.brightness),
+                shade(light)
+            ));
+        }
+    }
+
+    rows.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// A report for a device answering `/elgato/lights` with `lights`.
+    fn report(name: &str, address: &str, lights: Value) -> Report {
+        let lights = lights.as_array().unwrap();
+        Report {
+            name: name.to_string(),
+            address: address.parse().unwrap(),
+            reading: Snapshot {
+                status: serde_json::from_value(json!({
+                    "numberOfLights": lights.len(),
+                    "lights": lights,
+                }))
+                .unwrap(),
+                battery: None,
+            },
+        }
+    }
+
+    fn white() -> Report {
+        report(
+            "desk",
+            "192.168.0.25",
+            json!([{ "on": 1, "brightness": 35, "temperature": 222 }]),
+        )
+    }
+
+    fn strip() -> Report {
+        report(
+            "shelf",
+            "keylight.local:9124",
+            json!([{ "on": 1, "brightness": 80, "hue": 30.0, "saturation": 100.0 }]),
+        )
+    }
+
+    fn dual() -> Report {
+        report(
+            "studio",
+            "192.168.0.31",
+            json!([
+                { "on": 1, "brightness": 35, "temperature": 222 },
+                { "on": 0, "brightness": 50, "temperature": 344 },
+            ]),
+        )
+    }
+
+    fn rendered(reports: &[Report]) -> Value {
+        serde_json::from_str(&render(Format::Json, reports)).unwrap()
+    }
+
+    #[test]
+    fn json_for_a_white_light() {
+        assert_eq!(
+            rendered(&[white()]),
+            json!([{
+                "name": "desk",
+                "ip_address": "192.168.0.25",
+                "lights": [{
+                    "index": 0,
+                    "on": true,
+                    "brightness": 35,
+                    "temperature": { "kelvin": 4500, "mired": 222 },
+                }],
+                "battery": null,
+            }])
+        );
+    }
+
+    #[test]
+    fn json_for_a_color_light() {
+        assert_eq!(
+            rendered(&[strip()]),
+            json!([{
+                "name": "shelf",
+                "ip_address": "keylight.local:9124",
+                "lights": [{
+                    "index": 0,
+                    "on": true,
+                    "brightness": 80,
+                    "temperature": null,
+                    "color": { "hue": 30.0, "saturation": 100.0 },
+                }],
+                "battery": null,
+            }])
+        );
+    }
+
+    #[test]
+    fn json_for_a_device_with_several_lights() {
+        assert_eq!(
+            rendered(&[dual()]),
+            json!([{
+                "name": "studio",
+                "ip_address": "192.168.0.31",
+                "lights": [
+                    {
+                        "index": 0,
+                        "on": true,
+                        "brightness": 35,
+                        "temperature": { "kelvin": 4500, "mired": 222 },
+                    },
+                    {
+                        "index": 1,
+                        "on": false,
+                        "brightness": 50,
+                        "temperature": { "kelvin": 2900, "mired": 344 },
+                    },
+                ],
+                "battery": null,
+            }])
+        );
+    }
+
+    #[test]
+    fn text_is_labelled_only_when_needed() {
+        assert_eq!(
+            render(Format::Text, &[white()]),
+            "On, 35% brightness, 4500 K"
+        );
+        assert_eq!(
+            render(Format::Text, &[strip()]),
+            "On, 80% brightness, hue 30°, 100% saturation"
+        );
+        assert_eq!(
+            render(Format::Text, &[dual()]),
+            "Light 0: On, 35% brightness, 4500 K\nLight 1: Off, 50% brightness, 2900 K"
+        );
+        assert_eq!(
+            render(Format::Text, &[white(), strip()]),
+            "desk: On, 35% brightness, 4500 K\nshelf: On, 80% brightness, hue 30°, 100% saturation"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// Serves `/elgato/lights` and `/elgato/accessory-info` the way a Key Light does, applying
+/// every change it is sent.
+pub async fn fake_light() -> SocketAddr {
+    let lights = Arc::new(Mutex::new(json!({
+        "numberOfLights": 1,
+        "lights": [{ "on": 1, "brightness": 35, "temperature": 222 }],
+    })));
+    let make_service = make_service_fn(move |_| {
+        let lights = Arc::clone(&lights);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let lights = Arc::clone(&lights);
+                async move {
+                    let body = match (request.method(), request.uri().path()) {
+                        (&Method::PUT, "/elgato/lights") => {
+                            let update = hyper::body::to_bytes(request.into_body()).await?;
+                            let update: Value = serde_json::from_slice(&update).unwrap();
+                            let mut lights = lights.lock().unwrap();
+                            for (light, change) in lights["lights"]
+                                .as_array_mut()
+                                .unwrap()
+                                .iter_mut()
+                                .zip(update["lights"].as_array().unwrap())
+                            {
+                                for (key, value) in change.as_object().unwrap() {
+                                    light[key] = value.clone();
+                                }
+                            }
+                            lights.clone()
+                        }
+                        (_, "/elgato/lights") => lights.lock().unwrap().clone(),
+                        (_, "/elgato/accessory-info") => json!({
+                            "productName": "Elgato Key Light",
+                            "serialNumber": "AB12",
+                            "displayName": "Desk",
+                            "firmwareVersion": "1.0.3",
+                        }),
+                        _ => {
+                            return Ok(Response::builder().status(404).body(Body::empty()).unwrap())
+                        }
+                    };
+                    Ok::<_, hyper::Error>(Response::new(Body::from(body.to_string())))
+                }
+            }))
+        }
+    });
+
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+    address
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::str::FromStr;
+
+/// Defines a whole number that is only valid from `$min` to `$max`, whether it comes from the
+/// command line, the config and scenes files, or JSON.
+macro_rules! bounded {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident($repr:ty) in $min:literal..=$max:literal;
+        out_of_range = $out_of_range:literal,
+        not_a_number = $not_a_number:literal,
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+        #[serde(try_from = "i64")]
+        pub struct $name($repr);
+
+        impl $name {
+            pub const MIN: $name = $name($min);
+            pub const MAX: $name = $name($max);
+
+            /// Brings any value into range, for values that are already known to be close.
+            pub const fn clamped(value: $repr) -> $name {
+                if value < $name::MIN.0 {
+                    $name::MIN
+                } else if value > $name::MAX.0 {
+                    $name::MAX
+                } else {
+                    $name(value)
+                }
+            }
+
+            pub fn get(self) -> $repr {
+                self.0
+            }
+        }
+
+        impl TryFrom<i64> for $name {
+            type Error = String;
+
+            fn try_from(value: i64) -> Result<Self, Self::Error> {
+                match <$repr>::try_from(value) {
+                    Ok(value) if ($min..=$max).contains(&value) => Ok($name(value)),
+                    _ => Err(format!("{}, got {}", $out_of_range, value)),
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let value: i64 = s
+                    .parse()
+                    .map_err(|_| format!("'{}' {}", s, $not_a_number))?;
+                $name::try_from(value)
+            }
+        }
+
+        impl From<$name> for $repr {
+            fn from(value: $name) -> $repr {
+                value.0
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.0.serialize(serializer)
+            }
+        }
+    };
+}
+
+bounded! {
+    /// A brightness level in percent, from 0 to 100.
+    pub struct Brightness(u8) in 0..=100;
+    out_of_range = "Brightness must be between 0 and 100",
+    not_a_number = "is not a brightness level (0-100)",
+}
+
+bounded! {
+    /// A color temperature in Kelvin, from 2900 to 7000, the range Elgato's white lights support.
+    pub struct Kelvin(u32) in 2900..=7000;
+    out_of_range = "Temperature must be between 2900 and 7000 Kelvin",
+    not_a_number = "is not a temperature (2900-7000)",
+}
+
+bounded! {
+    /// A hue in degrees around the color wheel, from 0 to 360, for lights that can show color.
+    pub struct Hue(u16) in 0..=360;
+    out_of_range = "Hue must be between 0 and 360",
+    not_a_number = "is not a hue (0-360)",
+}
+
+bounded! {
+    /// A color saturation in percent, from 0 for white to 100 for the pure hue.
+    pub struct Saturation(u8) in 0..=100;
+    out_of_range = "Saturation must be between 0 and 100",
+    not_a_number = "is not a saturation level (0-100)",
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_accepts_0_through_100() {
+        assert_eq!("0".parse(), Ok(Brightness::MIN));
+        assert_eq!("100".parse(), Ok(Brightness::MAX));
+        assert!("101".parse::<Brightness>().is_err());
+        assert!("-1".parse::<Brightness>().is_err());
+        assert!("bright".parse::<Brightness>().is_err());
+    }
+
+    #[test]
+    fn kelvin_accepts_2900_through_7000() {
+        assert!("2899".parse::<Kelvin>().is_err());
+        assert_eq!("2900".parse(), Ok(Kelvin::MIN));
+        assert_eq!("7000".parse(), Ok(Kelvin::MAX));
+        assert!("7001".parse::<Kelvin>().is_err());
+    }
+
+    #[test]
+    fn clamped_values_stay_in_range() {
+        assert_eq!(Brightness::clamped(101), Brightness::MAX);
+        assert_eq!(Kelvin::clamped(0), Kelvin::MIN);
+        assert_eq!(Kelvin::clamped(9000), Kelvin::MAX);
+        assert_eq!(Hue::clamped(361), Hue::MAX);
+        assert_eq!(Saturation::clamped(101), Saturation::MAX);
+    }
+
+    #[test]
+    fn json_out_of_range_is_rejected() {
+        assert_eq!(
+            serde_json::from_str::<Brightness>("40").unwrap(),
+            Brightness(40)
+        );
+        assert!(serde_json::from_str::<Brightness>("101").is_err());
+        assert!(serde_json::from_str::<Kelvin>("2899").is_err());
+        assert_eq!(serde_json::to_string(&Kelvin::MIN).unwrap(), "2900");
+    }
+
+    #[test]
+    fn errors_say_what_was_expected() {
+        assert_eq!(
+            "-1".parse::<Brightness>(),
+            Err("Brightness must be between 0 and 100, got -1".to_string())
+        );
+        assert_eq!(
+            "9000".parse::<Kelvin>(),
+            Err("Temperature must be between 2900 and 7000 Kelvin, got 9000".to_string())
+        );
+        assert_eq!(
+            "70000".parse::<Hue>(),
+            Err("Hue must be between 0 and 360, got 70000".to_string())
+        );
+        assert_eq!(
+            "full".parse::<Saturation>(),
+            Err("'full' is not a saturation level (0-100)".to_string())
+        );
+    }
+}